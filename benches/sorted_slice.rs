@@ -0,0 +1,47 @@
+//! Compares linear and galloping group detection on sorted data with long
+//! runs. Run with `cargo bench`.
+
+extern crate groupby;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use groupby::GroupBySlice;
+
+
+const ROUNDS: u32 = 20;
+
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let mut groups = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        groups = black_box(f());
+    }
+    (start.elapsed() / ROUNDS, groups)
+}
+
+
+fn main() {
+    for &run_len in &[10u64, 1_000, 100_000] {
+        let data: Vec<u64> = (0..1_000_000).map(|x| x / run_len).collect();
+
+        let mut linear_calls = 0;
+        let (linear, groups) = time(|| {
+            data.group_by_key(|&x| { linear_calls += 1; x }).count()
+        });
+        let mut sorted_calls = 0;
+        let (sorted, sorted_groups) = time(|| {
+            data.group_by_sorted_key(|&x| { sorted_calls += 1; x }).count()
+        });
+        assert_eq!(groups, sorted_groups);
+
+        println!(
+            "run length {:>7}: group_by_key {:>10.2?} ({:>9} key calls), \
+             group_by_sorted_key {:>10.2?} ({:>9} key calls)",
+            run_len,
+            linear, linear_calls / ROUNDS as usize,
+            sorted, sorted_calls / ROUNDS as usize,
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Aggregators that can be combined into one spec and evaluated over every
+//! group in a single pass, see `GroupBy::agg`.
+//!
+//! ```
+//! use groupby::GroupByIterator;
+//! use groupby::agg::{Count, Max, Sum};
+//!
+//! let requests = vec![("a", 100u64, 3u32), ("a", 50, 9), ("b", 10, 1)];
+//! let stats: Vec<_> = requests.into_iter()
+//!     .group_by(|r| r.0)
+//!     .agg((Count, Sum(|r: &(&str, u64, u32)| r.1), Max(|r: &(&str, u64, u32)| r.2)))
+//!     .collect();
+//! assert_eq!(vec![("a", (2, 150, Some(9))), ("b", (1, 10, Some(1)))], stats);
+//! ```
+
+use core::mem;
+use core::ops::Add;
+
+
+pub trait Aggregator<T> {
+    type State;
+    type Output;
+
+    fn init(&mut self) -> Self::State;
+
+    fn update(&mut self, state: &mut Self::State, item: &T);
+
+    fn finish(&mut self, state: Self::State) -> Self::Output;
+}
+
+
+/// Number of items.
+pub struct Count;
+
+
+impl<T> Aggregator<T> for Count {
+    type State = usize;
+    type Output = usize;
+
+    fn init(&mut self) -> usize {
+        0
+    }
+
+    fn update(&mut self, state: &mut usize, _: &T) {
+        *state += 1;
+    }
+
+    fn finish(&mut self, state: usize) -> usize {
+        state
+    }
+}
+
+
+/// Sum of the values extracted by the wrapped function.
+pub struct Sum<G>(pub G);
+
+
+impl<T, G, V> Aggregator<T> for Sum<G> where
+    G: FnMut(&T) -> V,
+    V: Add<Output = V> + Default
+{
+    type State = V;
+    type Output = V;
+
+    fn init(&mut self) -> V {
+        V::default()
+    }
+
+    fn update(&mut self, state: &mut V, item: &T) {
+        let sum = mem::take(state);
+        *state = sum + (self.0)(item);
+    }
+
+    fn finish(&mut self, state: V) -> V {
+        state
+    }
+}
+
+
+/// Smallest value extracted by the wrapped function.
+pub struct Min<G>(pub G);
+
+
+impl<T, G, V> Aggregator<T> for Min<G> where
+    G: FnMut(&T) -> V,
+    V: Ord
+{
+    type State = Option<V>;
+    type Output = Option<V>;
+
+    fn init(&mut self) -> Option<V> {
+        None
+    }
+
+    fn update(&mut self, state: &mut Option<V>, item: &T) {
+        let value = (self.0)(item);
+        *state = Some(match state.take() {
+            Some(min) if min <= value => min,
+            _ => value
+        });
+    }
+
+    fn finish(&mut self, state: Option<V>) -> Option<V> {
+        state
+    }
+}
+
+
+/// Largest value extracted by the wrapped function.
+pub struct Max<G>(pub G);
+
+
+impl<T, G, V> Aggregator<T> for Max<G> where
+    G: FnMut(&T) -> V,
+    V: Ord
+{
+    type State = Option<V>;
+    type Output = Option<V>;
+
+    fn init(&mut self) -> Option<V> {
+        None
+    }
+
+    fn update(&mut self, state: &mut Option<V>, item: &T) {
+        let value = (self.0)(item);
+        *state = Some(match state.take() {
+            Some(max) if max > value => max,
+            _ => value
+        });
+    }
+
+    fn finish(&mut self, state: Option<V>) -> Option<V> {
+        state
+    }
+}
+
+
+macro_rules! tuple_aggregator {
+    ( $( $A:ident $idx:tt ),+ ) => (
+        impl<T, $($A),+> Aggregator<T> for ( $($A,)+ ) where
+            $( $A: Aggregator<T> ),+
+        {
+            type State = ( $( <$A as Aggregator<T>>::State, )+ );
+            type Output = ( $( <$A as Aggregator<T>>::Output, )+ );
+
+            fn init(&mut self) -> Self::State {
+                ( $( self.$idx.init(), )+ )
+            }
+
+            fn update(&mut self, state: &mut Self::State, item: &T) {
+                $( self.$idx.update(&mut state.$idx, item); )+
+            }
+
+            fn finish(&mut self, state: Self::State) -> Self::Output {
+                ( $( self.$idx.finish(state.$idx), )+ )
+            }
+        }
+    );
+}
+
+tuple_aggregator!(A 0);
+tuple_aggregator!(A 0, B 1);
+tuple_aggregator!(A 0, B 1, C 2);
+tuple_aggregator!(A 0, B 1, C 2, D 3);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4, G 5);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4, G 5, H 6);
+tuple_aggregator!(A 0, B 1, C 2, D 3, E 4, G 5, H 6, J 7);
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use super::{Aggregator, Count, Min};
+    use GroupByIterator;
+
+    // keeps every item whose value is a new maximum
+    struct Records;
+
+    impl Aggregator<i32> for Records {
+        type State = Vec<i32>;
+        type Output = Vec<i32>;
+
+        fn init(&mut self) -> Vec<i32> {
+            Vec::new()
+        }
+
+        fn update(&mut self, state: &mut Vec<i32>, item: &i32) {
+            if state.last().is_none_or(|last| item > last) {
+                state.push(*item);
+            }
+        }
+
+        fn finish(&mut self, state: Vec<i32>) -> Vec<i32> {
+            state
+        }
+    }
+
+    #[test]
+    fn custom_aggregator_in_spec() {
+        let stats: Vec<_> = vec![3, 1, 4, 1, 5, -9, -2, -6].into_iter()
+            .group_by(|&x| x > 0)
+            .agg((Count, Min(|&x: &i32| x), Records))
+            .collect();
+        assert_eq!(vec![
+            (true, (5, Some(1), vec![3, 4, 5])),
+            (false, (3, Some(-9), vec![-9, -2])),
+        ], stats);
+    }
+}
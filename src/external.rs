@@ -0,0 +1,507 @@
+//! Sort-and-group for inputs that do not fit in memory.
+//!
+//! The input is cut into runs that fit the memory budget, each run is
+//! stable-sorted by key and spilled to a temporary file, and the runs are
+//! then merged back into one sorted stream that is grouped by `GroupBy`.
+//! When there are more runs than may be open at once, they are first
+//! merged in passes into fewer, longer runs.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::string::String;
+use std::vec::{self, Vec};
+
+use {GroupBy, GroupIter, LendingIterator};
+
+
+/// Writes items to and reads them back from spilled runs.
+pub trait Codec<T> {
+    fn encode<W: Write>(&mut self, item: &T, writer: &mut W) -> io::Result<()>;
+
+    /// Reads the next item, or returns `Ok(None)` at the end of the run.
+    fn decode<R: BufRead>(&mut self, reader: &mut R) -> io::Result<Option<T>>;
+
+    /// Estimates the memory used by a buffered item, including any heap
+    /// data it owns.
+    fn item_size(&self, _item: &T) -> usize {
+        mem::size_of::<T>()
+    }
+}
+
+
+/// Stores `String` items one per line; items must not contain `'\n'`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinesCodec;
+
+
+impl Codec<String> for LinesCodec {
+    fn encode<W: Write>(&mut self, item: &String, writer: &mut W) -> io::Result<()> {
+        if item.contains('\n') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "item contains a newline"));
+        }
+        writer.write_all(item.as_bytes())?;
+        writer.write_all(b"\n")
+    }
+
+    fn decode<R: BufRead>(&mut self, reader: &mut R) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        line.pop();
+        Ok(Some(line))
+    }
+
+    fn item_size(&self, item: &String) -> usize {
+        mem::size_of::<String>() + item.capacity()
+    }
+}
+
+
+/// Configuration for `GroupByIterator::external_group_by`.
+pub struct ExternalSort<T, C> {
+    codec: C,
+    memory_budget: usize,
+    item_size: Option<fn(&T) -> usize>,
+    max_open_runs: usize,
+    temp_dir: PathBuf,
+}
+
+
+impl<T, C> ExternalSort<T, C> where
+    C: Codec<T>
+{
+    /// Defaults to a 64 MiB budget, items sized by `Codec::item_size`, at
+    /// most 64 runs open at once, and runs spilled to `env::temp_dir()`.
+    pub fn new(codec: C) -> Self {
+        ExternalSort {
+            codec,
+            memory_budget: 64 << 20,
+            item_size: None,
+            max_open_runs: 64,
+            temp_dir: env::temp_dir(),
+        }
+    }
+
+    /// Approximate number of bytes of items buffered before a run is
+    /// spilled.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Estimates the memory used by an item in place of the codec.
+    pub fn item_size(mut self, item_size: fn(&T) -> usize) -> Self {
+        self.item_size = Some(item_size);
+        self
+    }
+
+    /// Maximum number of runs merged at once, which bounds the number of
+    /// open files. Any more runs are first merged in passes into
+    /// intermediate runs.
+    ///
+    /// Panics if `runs` is less than 2.
+    pub fn max_open_runs(mut self, runs: usize) -> Self {
+        assert!(runs >= 2, "at least two runs must be merged at once");
+        self.max_open_runs = runs;
+        self
+    }
+
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+}
+
+
+// A temporary file removed on drop. It is only open while being written
+// or merged.
+struct SpillFile {
+    path: PathBuf,
+}
+
+
+impl SpillFile {
+    fn create(dir: &Path) -> io::Result<(Self, File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let n = COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+            let path = dir.join(format!("groupby-{}-{}.run", process::id(), n));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((SpillFile { path }, file)),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn open(&self) -> io::Result<BufReader<File>> {
+        File::open(&self.path).map(BufReader::new)
+    }
+}
+
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+
+enum Run<K, T> {
+    Memory(vec::IntoIter<(K, T)>),
+    Spilled(SpillFile),
+    // a spilled run opened for merging, removed once the run is dropped
+    Open { reader: BufReader<File>, _spilled: SpillFile },
+}
+
+
+// Next item of a run; ordered so that `BinaryHeap` pops the smallest key,
+// and for equal keys the earliest run, keeping the merge stable.
+struct Head<K, T> {
+    key: K,
+    run: usize,
+    item: T,
+}
+
+
+impl<K: Ord, T> Ord for Head<K, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key).then(other.run.cmp(&self.run))
+    }
+}
+
+
+impl<K: Ord, T> PartialOrd for Head<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<K: Ord, T> PartialEq for Head<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+
+impl<K: Ord, T> Eq for Head<K, T> { }
+
+
+fn spill<K, T, C>(buffer: &mut Vec<(K, T)>, codec: &mut C, dir: &Path) -> io::Result<Run<K, T>> where
+    K: Ord,
+    C: Codec<T>
+{
+    buffer.sort_by(|a, b| a.0.cmp(&b.0));
+    let (spilled, file) = SpillFile::create(dir)?;
+    let mut writer = BufWriter::new(file);
+    for (_, item) in buffer.drain(..) {
+        codec.encode(&item, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(Run::Spilled(spilled))
+}
+
+
+/// K-way merge of the sorted runs, yielding items in key order.
+pub struct ExternalMerge<T, F, K, C> {
+    runs: Vec<Run<K, T>>,
+    heap: BinaryHeap<Head<K, T>>,
+    codec: C,
+    key_func: F,
+    // key of the item last yielded, picked up by the `GroupBy` key function
+    key_slot: Rc<Cell<Option<K>>>,
+    error: Option<io::Error>,
+}
+
+
+impl<T, F, K, C> ExternalMerge<T, F, K, C> where
+    F: Fn(&T) -> K,
+    K: Ord,
+    C: Codec<T>
+{
+    fn open(runs: Vec<Run<K, T>>, codec: C, key_func: F) -> io::Result<Self> {
+        let runs = runs.into_iter()
+            .map(|run| match run {
+                Run::Spilled(spilled) => Ok(Run::Open { reader: spilled.open()?, _spilled: spilled }),
+                run => Ok(run),
+            })
+            .collect::<io::Result<_>>()?;
+        let mut merge = ExternalMerge {
+            runs,
+            heap: BinaryHeap::new(),
+            codec,
+            key_func,
+            key_slot: Rc::new(Cell::new(None)),
+            error: None,
+        };
+        for run in 0..merge.runs.len() {
+            merge.push_head(run)?;
+        }
+        Ok(merge)
+    }
+
+    // Writes out the whole merge as a single run, for a later pass.
+    fn spill(mut self, dir: &Path) -> io::Result<(Run<K, T>, C, F)> {
+        let (spilled, file) = SpillFile::create(dir)?;
+        let mut writer = BufWriter::new(file);
+        while let Some(item) = self.next() {
+            self.codec.encode(&item, &mut writer)?;
+        }
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        writer.flush()?;
+        Ok((Run::Spilled(spilled), self.codec, self.key_func))
+    }
+
+    fn read_run(&mut self, run: usize) -> io::Result<Option<(K, T)>> {
+        match self.runs[run] {
+            Run::Memory(ref mut iter) => Ok(iter.next()),
+            Run::Open { ref mut reader, .. } => {
+                Ok(self.codec.decode(reader)?.map(|item| ((self.key_func)(&item), item)))
+            }
+            Run::Spilled(_) => unreachable!("runs are opened before merging"),
+        }
+    }
+
+    fn push_head(&mut self, run: usize) -> io::Result<()> {
+        if let Some((key, item)) = self.read_run(run)? {
+            self.heap.push(Head { key, run, item });
+        }
+        Ok(())
+    }
+}
+
+
+impl<T, F, K, C> Iterator for ExternalMerge<T, F, K, C> where
+    F: Fn(&T) -> K,
+    K: Ord,
+    C: Codec<T>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+        let Head { key, run, item } = self.heap.pop()?;
+        if let Err(err) = self.push_head(run) {
+            self.error = Some(err);
+            self.heap.clear();
+        }
+        self.key_slot.set(Some(key));
+        Some(item)
+    }
+}
+
+
+/// Groups produced by `GroupByIterator::external_group_by`.
+///
+/// A failure to read back a spilled run ends the current group and is
+/// yielded in place of the next group, after which iteration stops.
+pub struct ExternalGroupBy<T, F, K, C, G> where
+    F: Fn(&T) -> K,
+    K: Ord,
+    C: Codec<T>,
+    G: FnMut(&T) -> K
+{
+    group_by: GroupBy<ExternalMerge<T, F, K, C>, G, K>,
+}
+
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn external_group_by<I, T, F, K, C>(iter: I, key_func: F, sort: ExternalSort<T, C>)
+    -> io::Result<ExternalGroupBy<T, F, K, C, impl FnMut(&T) -> K>>
+    where I: Iterator<Item = T>,
+          F: Fn(&T) -> K,
+          K: Ord,
+          C: Codec<T>
+{
+    let ExternalSort { mut codec, memory_budget, item_size, max_open_runs, temp_dir } = sort;
+    let mut runs = Vec::new();
+    let mut buffer = Vec::new();
+    let mut buffered = 0;
+    for item in iter {
+        let size = match item_size {
+            Some(item_size) => item_size(&item),
+            None => codec.item_size(&item),
+        };
+        if buffered + size > memory_budget && !buffer.is_empty() {
+            runs.push(spill(&mut buffer, &mut codec, &temp_dir)?);
+            buffered = 0;
+        }
+        buffered += size;
+        buffer.push((key_func(&item), item));
+    }
+    // the last run fits the budget, so it stays in memory
+    buffer.sort_by(|a, b| a.0.cmp(&b.0));
+    runs.push(Run::Memory(buffer.into_iter()));
+
+    // merging consecutive runs keeps equal keys in input order
+    let mut key_func = key_func;
+    while runs.len() > max_open_runs {
+        let mut pending = runs.into_iter().peekable();
+        runs = Vec::new();
+        while pending.peek().is_some() {
+            let chunk: Vec<_> = pending.by_ref().take(max_open_runs).collect();
+            let (run, next_codec, next_key_func) = ExternalMerge::open(chunk, codec, key_func)?
+                .spill(&temp_dir)?;
+            runs.push(run);
+            codec = next_codec;
+            key_func = next_key_func;
+        }
+    }
+
+    let merge = ExternalMerge::open(runs, codec, key_func)?;
+    // `GroupBy` calls its key function once for each item right after
+    // pulling it, so it can take the key the merge just computed
+    let key_slot = merge.key_slot.clone();
+    let slot_key_func = move |_: &T| key_slot.take().unwrap();
+    Ok(ExternalGroupBy { group_by: GroupBy::new(merge, slot_key_func) })
+}
+
+
+impl<T, F, K, C, G> LendingIterator for ExternalGroupBy<T, F, K, C, G> where
+    F: Fn(&T) -> K,
+    K: Ord,
+    C: Codec<T>,
+    G: FnMut(&T) -> K
+{
+    type Item<'a> = io::Result<(&'a K, GroupIter<'a, ExternalMerge<T, F, K, C>, G, K>)>
+        where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        if let Some(err) = self.group_by.iter.iter.error.take() {
+            return Some(Err(err));
+        }
+        if !self.group_by.skip_to_next_key() {
+            return self.group_by.iter.iter.error.take().map(Err);
+        }
+        Some(Ok(self.group_by.current_group()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use std::fs;
+    use std::io::{self, BufRead, Write};
+    use std::path::PathBuf;
+    use super::{Codec, ExternalSort, LinesCodec};
+    use {GroupByIterator, LendingIterator};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir()
+            .join(format!("groupby-test-{}-{}", name, ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn spills_and_merges_runs() {
+        let dir = temp_dir("spill");
+        let lines: Vec<String> = (0..100).map(|i| format!("{}-{}", (i * 7) % 10, i)).collect();
+        let sort = ExternalSort::new(LinesCodec)
+            .item_size(|line: &String| line.len())
+            .memory_budget(40)
+            .temp_dir(&dir);
+        let mut groups = lines.into_iter()
+            .external_group_by(|line| line[..1].to_string(), sort)
+            .unwrap();
+        assert!(fs::read_dir(&dir).unwrap().count() > 1);
+
+        let mut seen = Vec::new();
+        while let Some(group) = groups.next() {
+            let (key, grp) = group.unwrap();
+            let items: Vec<String> = grp.collect();
+            assert_eq!(10, items.len());
+            // stable: items of each key keep their input order
+            let indices: Vec<u32> = items.iter().map(|l| l[2..].parse().unwrap()).collect();
+            let mut sorted = indices.clone();
+            sorted.sort();
+            assert_eq!(sorted, indices);
+            seen.push(key.clone());
+        }
+        assert_eq!((0..10).map(|k| k.to_string()).collect::<Vec<_>>(), seen);
+
+        drop(groups);
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn merges_in_passes_with_borrowed_keys() {
+        let dir = temp_dir("passes");
+        let names = ["even".to_string(), "odd".to_string()];
+        // one run per item, far more than may be open at once
+        let sort = ExternalSort::new(LinesCodec)
+            .memory_budget(1)
+            .max_open_runs(4)
+            .temp_dir(&dir);
+        let mut groups = (0..200).map(|i| i.to_string())
+            .external_group_by(|line| &names[line.parse::<usize>().unwrap() % 2], sort)
+            .unwrap();
+        assert!(fs::read_dir(&dir).unwrap().count() <= 4);
+
+        let mut seen = Vec::new();
+        while let Some(group) = groups.next() {
+            let (key, grp) = group.unwrap();
+            seen.push(((*key).clone(), grp.map(|l| l.parse().unwrap()).collect::<Vec<u32>>()));
+        }
+        assert_eq!(vec![
+            ("even".to_string(), (0..200).filter(|i| i % 2 == 0).collect()),
+            ("odd".to_string(), (0..200).filter(|i| i % 2 == 1).collect()),
+        ], seen);
+
+        drop(groups);
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    // Fails to read back the item "bad".
+    struct FailingCodec;
+
+    impl Codec<String> for FailingCodec {
+        fn encode<W: Write>(&mut self, item: &String, writer: &mut W) -> io::Result<()> {
+            LinesCodec.encode(item, writer)
+        }
+
+        fn decode<R: BufRead>(&mut self, reader: &mut R) -> io::Result<Option<String>> {
+            match LinesCodec.decode(reader)? {
+                Some(ref line) if line == "bad" => Err(io::Error::new(io::ErrorKind::InvalidData, "bad")),
+                line => Ok(line),
+            }
+        }
+    }
+
+    #[test]
+    fn read_errors_are_yielded() {
+        let dir = temp_dir("errors");
+        // runs of two items, the second of which fails after yielding "c"
+        let sort = ExternalSort::new(FailingCodec)
+            .item_size(|_| 1)
+            .memory_budget(2)
+            .temp_dir(&dir);
+        let lines = vec!["a", "b", "c", "bad", "d"].into_iter().map(String::from);
+        let mut groups = lines.external_group_by(|_| 0, sort).unwrap();
+        let items: Vec<String> = groups.next().unwrap().unwrap().1.collect();
+        assert_eq!(vec!["a", "b", "c"], items);
+        assert_eq!(io::ErrorKind::InvalidData, groups.next().unwrap().err().unwrap().kind());
+        assert!(groups.next().is_none());
+
+        drop(groups);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
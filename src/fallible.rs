@@ -0,0 +1,228 @@
+use core::error::Error;
+use core::fmt;
+
+use {GroupBy, GroupIter, KeyedIter, LendingIterator};
+
+
+/// Yields the `Ok` values of `iter` up to its first `Err`, which it keeps.
+pub struct UntilErr<I, E> {
+    iter: I,
+    error: Option<E>,
+    stopped: bool,
+}
+
+
+impl<I, T, E> Iterator for UntilErr<I, E> where
+    I: Iterator<Item = Result<T, E>>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.stopped {
+            return None;
+        }
+        match self.iter.next() {
+            Some(Ok(item)) => Some(item),
+            Some(Err(err)) => {
+                self.error = Some(err);
+                self.stopped = true;
+                None
+            },
+            None => {
+                self.stopped = true;
+                None
+            }
+        }
+    }
+}
+
+
+/// Groups of the `Ok` items of an iterator over `Result`s.
+///
+/// An `Err` item ends the current group and is then yielded in place of the
+/// next group, after which iteration stops.
+pub struct TryGroupBy<I, E, F, K> where
+    UntilErr<I, E>: Iterator,
+    F: FnMut(&<UntilErr<I, E> as Iterator>::Item) -> K,
+{
+    group_by: GroupBy<UntilErr<I, E>, F, K>,
+}
+
+
+impl<I, T, E, F, K> TryGroupBy<I, E, F, K> where
+    I: Iterator<Item = Result<T, E>>,
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    pub(crate) fn new(iter: I, key_func: F) -> Self {
+        let iter = UntilErr { iter, error: None, stopped: false };
+        TryGroupBy { group_by: GroupBy::new(iter, key_func) }
+    }
+}
+
+
+impl<I, E, F, K> LendingIterator for TryGroupBy<I, E, F, K> where
+    UntilErr<I, E>: Iterator,
+    F: FnMut(&<UntilErr<I, E> as Iterator>::Item) -> K,
+    K: PartialEq
+{
+    type Item<'a> = Result<(&'a K, GroupIter<'a, UntilErr<I, E>, F, K>), E> where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        if !self.group_by.skip_to_next_key() {
+            return self.group_by.iter.iter.error.take().map(Err);
+        }
+        Some(Ok(self.group_by.current_group()))
+    }
+}
+
+
+/// An item whose key function failed, as yielded by `TryKeyGroupBy`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyError<T, E> {
+    pub item: T,
+    pub error: E,
+    /// Zero-based position of `item` in the input.
+    pub position: usize,
+}
+
+
+impl<T, E: fmt::Display> fmt::Display for KeyError<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to compute key of item at position {}: {}", self.position, self.error)
+    }
+}
+
+
+impl<T: fmt::Debug, E: Error + 'static> Error for KeyError<T, E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+
+pub struct TryKeyGroupIter<'a, I, F, K, E> where
+    I: Iterator + 'a,
+    F: FnMut(&I::Item) -> Result<K, E> + 'a,
+    K: 'a,
+    E: 'a
+{
+    iter: &'a mut KeyedIter<I, F, Result<K, E>>,
+    first: &'a mut Option<I::Item>,
+    key: &'a K,
+}
+
+
+impl<'a, I, F, K, E> Iterator for TryKeyGroupIter<'a, I, F, K, E> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> Result<K, E>,
+    K: PartialEq
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.first.take() {
+            return Some(item);
+        }
+        match self.iter.peek_key() {
+            Some(Ok(key)) if key == self.key => self.iter.next().map(|(_, item)| item),
+            _ => None
+        }
+    }
+}
+
+
+/// Groups by a fallible key function.
+///
+/// An item whose key cannot be computed ends the current group and is
+/// yielded as a `KeyError` in place of the next group; grouping then
+/// carries on with the following items.
+pub struct TryKeyGroupBy<I, F, K, E> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> Result<K, E>,
+{
+    iter: KeyedIter<I, F, Result<K, E>>,
+    first: Option<I::Item>,
+    current_key: Option<K>,
+}
+
+
+impl<I, F, K, E> TryKeyGroupBy<I, F, K, E> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> Result<K, E>,
+    K: PartialEq
+{
+    pub(crate) fn new(iter: I, key_func: F) -> Self {
+        TryKeyGroupBy {
+            iter: KeyedIter { iter, key_func, peeked: None, position: 0 },
+            first: None,
+            current_key: None,
+        }
+    }
+}
+
+
+impl<I, F, K, E> LendingIterator for TryKeyGroupBy<I, F, K, E> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> Result<K, E>,
+    K: PartialEq
+{
+    type Item<'a> = Result<(&'a K, TryKeyGroupIter<'a, I, F, K, E>), KeyError<I::Item, E>>
+        where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        self.first = None;
+        while let Some(Ok(key)) = self.iter.peek_key() {
+            if Some(key) != self.current_key.as_ref() {
+                break;
+            }
+            self.iter.next();
+        }
+        self.current_key = None;
+        let (key, item) = self.iter.next()?;
+        match key {
+            Err(error) => {
+                let position = self.iter.position - 1;
+                Some(Err(KeyError { item, error, position }))
+            },
+            Ok(key) => {
+                self.current_key = Some(key);
+                self.first = Some(item);
+                let TryKeyGroupBy { ref mut iter, ref mut first, ref current_key } = *self;
+                let key = current_key.as_ref().unwrap();
+                Some(Ok((key, TryKeyGroupIter { iter, first, key })))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use {GroupByIterator, LendingIterator};
+
+    #[test]
+    fn stops_after_first_error() {
+        let rows = vec![Ok(1), Ok(1), Ok(2), Err("bad row"), Ok(2), Ok(3)];
+        let mut grp = rows.into_iter().try_group_by(|&x| x);
+        let mut results = Vec::new();
+        while let Some(group) = grp.next() {
+            results.push(group.map(|(k, g)| (*k, g.count())));
+        }
+        assert_eq!(vec![Ok((1, 2)), Ok((2, 1)), Err("bad row")], results);
+        assert!(grp.next().is_none());
+    }
+
+    #[test]
+    fn reports_failing_key() {
+        let lines = vec!["a,1", "a,2", "b", "a,3", "c,4"];
+        let mut grp = lines.into_iter()
+            .group_by_try_key(|line| line.split(',').nth(1).ok_or("missing column").map(|_| &line[..1]));
+        let mut results = Vec::new();
+        while let Some(group) = grp.next() {
+            results.push(group.map(|(k, g)| (*k, g.count())).map_err(|e| (e.item, e.position)));
+        }
+        assert_eq!(vec![Ok(("a", 2)), Err(("b", 2)), Ok(("a", 1)), Ok(("c", 1))], results);
+    }
+}
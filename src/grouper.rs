@@ -0,0 +1,86 @@
+use alloc::vec::Vec;
+use core::mem;
+
+
+/// Push-based counterpart of `GroupBy` for callback-driven code.
+///
+/// Items are fed one at a time and a group is handed back as soon as an
+/// item with a different key shows up, the same boundary `GroupBy` draws.
+///
+/// ```
+/// use groupby::Grouper;
+///
+/// let mut grouper = Grouper::new(|x: &i32| x / 2);
+/// assert_eq!(None, grouper.push(1));
+/// assert_eq!(None, grouper.push(1));
+/// assert_eq!(Some((0, vec![1, 1])), grouper.push(2));
+/// assert_eq!(None, grouper.push(3));
+/// assert_eq!(Some((1, vec![2, 3])), grouper.finish());
+/// assert_eq!(None, grouper.finish());
+/// ```
+pub struct Grouper<K, T, F> where
+    F: FnMut(&T) -> K,
+{
+    key_func: F,
+    current_key: Option<K>,
+    items: Vec<T>,
+}
+
+
+impl<K, T, F> Grouper<K, T, F> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    /// `f` is called exactly once per pushed item, in order.
+    pub fn new(f: F) -> Self {
+        Grouper { key_func: f, current_key: None, items: Vec::new() }
+    }
+
+    /// Adds an item, returning the previous group if `item` starts a new one.
+    pub fn push(&mut self, item: T) -> Option<(K, Vec<T>)> {
+        let key = (self.key_func)(&item);
+        let done = match self.current_key {
+            Some(ref current) if *current != key => self.take_group(),
+            _ => None
+        };
+        if self.current_key.is_none() {
+            self.current_key = Some(key);
+        }
+        self.items.push(item);
+        done
+    }
+
+    /// Returns the group in progress, if any, leaving the grouper empty and
+    /// ready for more items.
+    pub fn finish(&mut self) -> Option<(K, Vec<T>)> {
+        self.take_group()
+    }
+
+    /// Key of the group in progress.
+    pub fn current_key(&self) -> Option<&K> {
+        self.current_key.as_ref()
+    }
+
+    fn take_group(&mut self) -> Option<(K, Vec<T>)> {
+        let key = self.current_key.take()?;
+        Some((key, mem::take(&mut self.items)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use super::Grouper;
+    use GroupByIterator;
+
+    #[test]
+    fn matches_group_by_owned() {
+        let data = vec![3, 3, 1, 4, 4, 4, 1, 5, 9, 9];
+        let mut grouper = Grouper::new(|&x: &i32| x % 2 == 0);
+        let mut pushed: Vec<_> = data.iter().filter_map(|&x| grouper.push(x)).collect();
+        pushed.extend(grouper.finish());
+        let pulled: Vec<_> = data.into_iter().group_by_owned(|&x| x % 2 == 0).collect();
+        assert_eq!(pulled, pushed);
+    }
+}
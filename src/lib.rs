@@ -1,177 +1,803 @@
 //! GroupBy iterator implemented without use of RefCell.
 //!
+//! Groups borrow from the `GroupBy` they came from, so it is a
+//! `LendingIterator` rather than an `Iterator`.
+//!
 //! Usage:
 //! 
 //! ```
-//! use groupby::GroupByIterator;
-//! for (key, grp) in vec![1,1,1,1,2,3,3,4].into_iter().group_by(|x| x/2).by_ref() {
+//! use groupby::{GroupByIterator, LendingIterator};
+//! let mut groups = vec![1,1,1,1,2,3,3,4].into_iter().group_by(|x| x/2);
+//! while let Some((key, grp)) = groups.next() {
 //!     println!("Key {:?}", key);
 //!     for item in grp.take(2) {
 //!         println!(" - {:?}", item);
 //!     }
 //! }
 //! ```
+//!
+//! The crate is `no_std`. The default `std` feature enables adaptors that
+//! need I/O, and the `alloc` feature, which it implies, enables the ones
+//! that buffer items or hash keys. Without `std`, hash grouping returns
+//! `hashbrown` maps and sets instead of those of `std::collections`. The
+//! `async` feature adds `GroupByStream` for `futures` streams, and the
+//! `rayon` feature adds parallel grouping.
 
-use std::mem;
-use std::iter::Peekable;
+#![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+#[macro_use]
+extern crate std;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+extern crate hashbrown;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(all(test, feature = "async"))]
+extern crate futures;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
-macro_rules! reset_lifetime {
-    ( &mut $t:ty, $v:expr ) => (
-        unsafe {
-            // attach lifetime declared by caller
-            mem::transmute::<*mut $t, &mut $t>(
-                // lose lifetime
-                mem::transmute::<&mut $t, *mut $t>(
-                    $v
-                )
-            )
-        }
-    );
-    ( &$t:ty, $v:expr ) => (
-        unsafe {
-            // attach lifetime declared by caller
-            mem::transmute::<*const $t, &$t>(
-                // lose lifetime
-                mem::transmute::<&$t, *const $t>(
-                    $v
-                )
-            )
+#[cfg(feature = "alloc")]
+use alloc::vec::{self, Vec};
+use core::iter::{self, Sum};
+#[cfg(feature = "alloc")]
+use core::ops::Sub;
+#[cfg(feature = "alloc")]
+use core::hash::Hash;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::io;
+
+pub mod agg;
+#[cfg(feature = "std")]
+mod external;
+mod fallible;
+#[cfg(feature = "alloc")]
+mod grouper;
+#[cfg(feature = "rayon")]
+mod par;
+mod slice;
+#[cfg(feature = "alloc")]
+mod sorted;
+mod strict;
+#[cfg(feature = "async")]
+mod stream;
+
+pub use agg::Aggregator;
+#[cfg(feature = "std")]
+pub use external::{Codec, ExternalGroupBy, ExternalMerge, ExternalSort, LinesCodec};
+pub use fallible::{KeyError, TryGroupBy, TryKeyGroupBy, TryKeyGroupIter, UntilErr};
+#[cfg(feature = "alloc")]
+pub use grouper::Grouper;
+#[cfg(feature = "rayon")]
+pub use par::{ParGroupBySlice, ParGroupMap, ParSliceGroupBy, ShardedGroups};
+pub use slice::{GroupBySlice, SliceGroupBy, SliceGroupByMut, SortedSliceGroupBy};
+#[cfg(feature = "alloc")]
+pub use sorted::SortedGroups;
+pub use strict::{ClosedKeys, Monotonic, NonContiguousKey, StrictGroupBy};
+#[cfg(feature = "async")]
+pub use stream::{GroupByStream, GroupStream, NextGroup, StreamGroupBy};
+#[cfg(all(feature = "async", feature = "alloc"))]
+pub use stream::StreamGroupByOwned;
+
+
+/// Like `Iterator`, but each item may borrow from the iterator itself.
+pub trait LendingIterator {
+    type Item<'a> where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>>;
+
+    fn by_ref(&mut self) -> &mut Self where
+        Self: Sized
+    {
+        self
+    }
+
+    fn for_each<G>(mut self, mut f: G) where
+        Self: Sized,
+        G: FnMut(Self::Item<'_>)
+    {
+        while let Some(item) = self.next() {
+            f(item);
         }
-    );
+    }
+}
+
+
+impl<L> LendingIterator for &mut L where
+    L: LendingIterator
+{
+    type Item<'a> = L::Item<'a> where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        (**self).next()
+    }
 }
 
 
-pub struct GroupIter<I, F, K> where
+struct KeyedIter<I, F, K> where
     I: Iterator,
-    F: Fn(&I::Item) -> K
+    F: FnMut(&I::Item) -> K,
 {
-    iter: Peekable<I>,
+    iter: I,
     key_func: F,
-    current_key: Option<K>,
+    peeked: Option<(K, I::Item)>,
+    // number of items pulled from `iter`
+    position: usize,
 }
 
 
-impl<I, F, K> Iterator for GroupIter<I, F, K> where
+impl<I, F, K> KeyedIter<I, F, K> where
     I: Iterator,
-    F: Fn(&I::Item) -> K,
+    F: FnMut(&I::Item) -> K,
+{
+    fn peek_key(&mut self) -> Option<&K> {
+        if self.peeked.is_none() {
+            let key_func = &mut self.key_func;
+            self.peeked = self.iter.next().map(|item| (key_func(&item), item));
+            self.position += self.peeked.is_some() as usize;
+        }
+        self.peeked.as_ref().map(|(key, _)| key)
+    }
+
+    fn next(&mut self) -> Option<(K, I::Item)> {
+        self.peek_key();
+        self.peeked.take()
+    }
+}
+
+
+pub struct GroupIter<'a, I, F, K> where
+    I: Iterator + 'a,
+    F: FnMut(&I::Item) -> K + 'a,
+    K: 'a
+{
+    iter: &'a mut KeyedIter<I, F, K>,
+    first: &'a mut Option<I::Item>,
+    key: &'a K,
+}
+
+
+impl<'a, I, F, K> Iterator for GroupIter<'a, I, F, K> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
     K: PartialEq
 {
     type Item = I::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.peek_key() {
-            None => None,
-            key => {
-                if key == self.current_key {
-                    self.iter.next()
-                } else {
-                    None
-                }
-            }
+        if let Some(item) = self.first.take() {
+            return Some(item);
+        }
+        if self.iter.peek_key() == Some(self.key) {
+            self.iter.next().map(|(_, item)| item)
+        } else {
+            None
         }
     }
 }
 
 
-impl<I, F, K> GroupIter<I, F, K> where
+/// Callbacks driven by `GroupBy::visit` at group boundaries.
+pub trait GroupVisitor<K, T> {
+    fn on_group_start(&mut self, _key: &K) { }
+
+    fn on_item(&mut self, key: &K, item: T);
+
+    fn on_group_end(&mut self, _key: &K) { }
+}
+
+
+pub struct GroupBy<I, F, K> where
     I: Iterator,
-    F: Fn(&I::Item) -> K,
+    F: FnMut(&I::Item) -> K,
+{
+    iter: KeyedIter<I, F, K>,
+    first: Option<I::Item>,
+    current_key: Option<K>,
+}
+
+
+impl<I, F, K> GroupBy<I, F, K> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
     K: PartialEq
 {
-    fn peek_key(&mut self) -> Option<K> {
-        match self.iter.peek() {
-            None => None,
-            Some(item) => Some((self.key_func)(item))
+    fn new(iter: I, key_func: F) -> Self {
+        GroupBy {
+            iter: KeyedIter { iter, key_func, peeked: None, position: 0 },
+            first: None,
+            current_key: None,
+        }
+    }
+
+    // Drops whatever is left of the current group.
+    fn skip_group(&mut self) {
+        self.first = None;
+        while let Some(key) = self.iter.peek_key() {
+            if Some(key) != self.current_key.as_ref() {
+                break;
+            }
+            self.iter.next();
         }
     }
 
     fn skip_to_next_key(&mut self) -> bool {
-        match self.current_key {
-            None => {
-                match self.peek_key() {
-                    None => false,
-                    key => {
-                        self.current_key = key;
-                        true
-                    }
-                }
-            },
-            _ => loop {
-                match self.peek_key() {
-                    None => { return false },
-                    key => {
-                        if key == self.current_key {
-                            self.iter.next();
-                        } else {
-                            self.current_key = key;
-                            return true;
-                        }
-                    }
-                }
+        self.skip_group();
+        match self.iter.next() {
+            None => false,
+            Some((key, item)) => {
+                // the key moves into `current_key`, the item is handed out first
+                self.current_key = Some(key);
+                self.first = Some(item);
+                true
             }
         }
     }
+
+    fn current_group(&mut self) -> (&K, GroupIter<'_, I, F, K>) {
+        let GroupBy { ref mut iter, ref mut first, ref current_key } = *self;
+        let key = current_key.as_ref().unwrap();
+        (key, GroupIter { iter, first, key })
+    }
+
+    // Runs `f` on the next group, then skips whatever it left unconsumed
+    // and hands out the key by value.
+    fn next_with<R, G>(&mut self, f: G) -> Option<(K, R)> where
+        G: FnOnce(&K, &mut GroupIter<'_, I, F, K>) -> R
+    {
+        let result = match LendingIterator::next(self) {
+            None => return None,
+            Some((key, mut grp)) => {
+                let result = f(key, &mut grp);
+                grp.for_each(drop);
+                result
+            }
+        };
+        // the group was consumed entirely, so the next call starts afresh
+        let key = self.current_key.take().unwrap();
+        Some((key, result))
+    }
+
+    fn next_folded<A, N, G>(&mut self, init: N, fold: G) -> Option<(K, A)> where
+        N: FnOnce(&K) -> A,
+        G: FnMut(A, I::Item) -> A
+    {
+        self.next_with(|key, grp| grp.fold(init(key), fold))
+    }
+
+    /// Folds every group starting from a clone of `init`, yielding
+    /// `(key, accumulator)` pairs.
+    pub fn aggregate<A, G>(self, init: A, fold: G) -> Aggregate<I, F, K, A, G> where
+        A: Clone,
+        G: FnMut(A, I::Item) -> A
+    {
+        Aggregate { group_by: self, init, fold }
+    }
+
+    /// Like `aggregate`, but each group's initial accumulator is built from
+    /// its key.
+    pub fn fold_groups<A, N, G>(self, init: N, fold: G) -> FoldGroups<I, F, K, N, G> where
+        N: FnMut(&K) -> A,
+        G: FnMut(A, I::Item) -> A
+    {
+        FoldGroups { group_by: self, init, fold }
+    }
+
+    /// Evaluates an aggregation spec, such as a tuple of aggregators, over
+    /// every group in a single pass.
+    pub fn agg<A>(mut self, mut spec: A) -> impl Iterator<Item = (K, A::Output)> where
+        A: Aggregator<I::Item>
+    {
+        iter::from_fn(move || self.next_with(|_, grp| {
+            let mut state = spec.init();
+            for item in grp {
+                spec.update(&mut state, &item);
+            }
+            spec.finish(state)
+        }))
+    }
+
+    /// Yields the number of items in each group.
+    pub fn counts(mut self) -> impl Iterator<Item = (K, usize)> {
+        iter::from_fn(move || self.next_with(|_, grp| grp.count()))
+    }
+
+    /// Yields the sum of each group.
+    pub fn sums<S>(mut self) -> impl Iterator<Item = (K, S)> where
+        S: Sum<I::Item>
+    {
+        iter::from_fn(move || self.next_with(|_, grp| grp.sum()))
+    }
+
+    /// Yields the arithmetic mean of each group.
+    pub fn means(self) -> impl Iterator<Item = (K, f64)> where
+        I::Item: Into<f64>
+    {
+        self.means_by(Into::into)
+    }
+
+    /// Like `means`, converting items with `f`, e.g. `|x| x as f64` for
+    /// integer types that do not implement `Into<f64>`.
+    pub fn means_by<G>(mut self, mut f: G) -> impl Iterator<Item = (K, f64)> where
+        G: FnMut(I::Item) -> f64
+    {
+        iter::from_fn(move || self.next_with(|_, grp| {
+            let (n, sum) = grp.fold((0usize, 0.0), |(n, sum), item| (n + 1, sum + f(item)));
+            sum / n as f64
+        }))
+    }
+
+    /// Yields the item with the smallest `f(item)` in each group, the first
+    /// one on ties.
+    pub fn min_by_key<B, G>(mut self, mut f: G) -> impl Iterator<Item = (K, I::Item)> where
+        B: Ord,
+        G: FnMut(&I::Item) -> B
+    {
+        iter::from_fn(move || self.next_with(|_, grp| grp.min_by_key(&mut f).unwrap()))
+    }
+
+    /// Yields the item with the largest `f(item)` in each group, the last
+    /// one on ties.
+    pub fn max_by_key<B, G>(mut self, mut f: G) -> impl Iterator<Item = (K, I::Item)> where
+        B: Ord,
+        G: FnMut(&I::Item) -> B
+    {
+        iter::from_fn(move || self.next_with(|_, grp| grp.max_by_key(&mut f).unwrap()))
+    }
+
+    /// Yields the first item of each group.
+    pub fn firsts(mut self) -> impl Iterator<Item = (K, I::Item)> {
+        iter::from_fn(move || self.next_with(|_, grp| grp.next().unwrap()))
+    }
+
+    /// Yields the last item of each group.
+    pub fn lasts(mut self) -> impl Iterator<Item = (K, I::Item)> {
+        iter::from_fn(move || self.next_with(|_, grp| grp.last().unwrap()))
+    }
+
+    /// Feeds every remaining group to `visitor`, item by item, without
+    /// buffering.
+    pub fn visit<V>(mut self, visitor: &mut V) where
+        V: GroupVisitor<K, I::Item>
+    {
+        while let Some((key, grp)) = LendingIterator::next(&mut self) {
+            visitor.on_group_start(key);
+            for item in grp {
+                visitor.on_item(key, item);
+            }
+            visitor.on_group_end(key);
+        }
+    }
 }
 
 
-pub struct GroupBy<I, F, K> where
+impl<I, F, K> LendingIterator for GroupBy<I, F, K> where
     I: Iterator,
-    F: Fn(&I::Item) -> K,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq
 {
-    group_iter: GroupIter<I, F, K>,
+    type Item<'a> = (&'a K, GroupIter<'a, I, F, K>) where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        if !self.skip_to_next_key() {
+            return None;
+        }
+        Some(self.current_group())
+    }
 }
 
 
-impl<I, F, K> GroupBy<I, F, K> where
+#[cfg(feature = "alloc")]
+pub struct GroupByOwned<I, F, K> where
     I: Iterator,
-    F: Fn(&I::Item) -> K,
+    F: FnMut(&I::Item) -> K,
+{
+    group_by: GroupBy<I, F, K>,
+}
+
+
+#[cfg(feature = "alloc")]
+impl<I, F, K> Iterator for GroupByOwned<I, F, K> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
     K: PartialEq
 {
-    fn new(iter: I, key_func: F) -> Self {
-        GroupBy {
-            group_iter: GroupIter {
-                iter: iter.peekable(),
-                key_func,
-                current_key: None,
-            }
-        }
+    type Item = (K, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.group_by.next_folded(|_| Vec::new(), |mut items, item| {
+            items.push(item);
+            items
+        })
     }
+}
 
-    pub fn by_ref(&mut self) -> &mut Self {
-        self
+
+pub struct Aggregate<I, F, K, A, G> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+{
+    group_by: GroupBy<I, F, K>,
+    init: A,
+    fold: G,
+}
+
+
+impl<I, F, K, A, G> Iterator for Aggregate<I, F, K, A, G> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq,
+    A: Clone,
+    G: FnMut(A, I::Item) -> A
+{
+    type Item = (K, A);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let init = &self.init;
+        self.group_by.next_folded(|_| init.clone(), &mut self.fold)
     }
 }
 
 
-impl<'a, I, F, K> Iterator for &'a mut GroupBy<I, F, K> where
+pub struct FoldGroups<I, F, K, N, G> where
     I: Iterator,
-    F: Fn(&I::Item) -> K,
-    K: PartialEq
+    F: FnMut(&I::Item) -> K,
+{
+    group_by: GroupBy<I, F, K>,
+    init: N,
+    fold: G,
+}
+
+
+impl<I, F, K, A, N, G> Iterator for FoldGroups<I, F, K, N, G> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq,
+    N: FnMut(&K) -> A,
+    G: FnMut(A, I::Item) -> A
 {
-    type Item = (&'a K, &'a mut GroupIter<I, F, K>);
+    type Item = (K, A);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.group_iter.skip_to_next_key() {
-            None
+        self.group_by.next_folded(&mut self.init, &mut self.fold)
+    }
+}
+
+
+struct AdjacentIter<I, P> where
+    I: Iterator,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    iter: I,
+    pred: P,
+    peeked: Option<I::Item>,
+    started: bool,
+    joined: bool,
+}
+
+
+impl<I, P> AdjacentIter<I, P> where
+    I: Iterator,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    fn next(&mut self) -> Option<I::Item> {
+        if !self.started {
+            self.peeked = self.iter.next();
+            self.started = true;
+        }
+        let item = self.peeked.take()?;
+        self.peeked = self.iter.next();
+        // whether the new peeked item belongs to the same group as `item`
+        self.joined = match self.peeked {
+            None => false,
+            Some(ref next) => (self.pred)(&item, next)
+        };
+        Some(item)
+    }
+}
+
+
+pub struct GroupEqIter<'a, I, P> where
+    I: Iterator + 'a,
+    P: FnMut(&I::Item, &I::Item) -> bool + 'a,
+{
+    iter: &'a mut AdjacentIter<I, P>,
+    first: &'a mut Option<I::Item>,
+}
+
+
+impl<'a, I, P> Iterator for GroupEqIter<'a, I, P> where
+    I: Iterator,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.first.take() {
+            return Some(item);
+        }
+        if self.iter.joined {
+            self.iter.next()
         } else {
-            Some((
-                reset_lifetime!(&K, self.group_iter.current_key.as_ref().unwrap()),
-                reset_lifetime!(&mut GroupIter<I, F, K>, &mut self.group_iter),
-            ))
+            None
         }
     }
 }
 
 
+pub struct GroupByEq<I, P> where
+    I: Iterator,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    iter: AdjacentIter<I, P>,
+    first: Option<I::Item>,
+}
+
+
+impl<I, P> LendingIterator for GroupByEq<I, P> where
+    I: Iterator,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    type Item<'a> = GroupEqIter<'a, I, P> where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        self.first = None;
+        while self.iter.joined {
+            self.iter.next();
+        }
+        self.first = Some(self.iter.next()?);
+        Some(GroupEqIter { iter: &mut self.iter, first: &mut self.first })
+    }
+}
+
+
 pub trait GroupByIterator {
+    /// Groups consecutive items with equal keys.
+    ///
+    /// `f` is called exactly once per element, in iteration order, so it
+    /// may keep state between calls.
     fn group_by<F, K>(self, f: F) -> GroupBy<Self, F, K>
         where Self: Sized + Iterator,
-              F: Fn(&Self::Item) -> K,
+              F: FnMut(&Self::Item) -> K,
               K: PartialEq
     {
         GroupBy::new(self, f)
     }
+
+    /// Groups the `Ok` values of an iterator over `Result`s by `f`.
+    ///
+    /// The first `Err` ends the current group and is yielded in place of
+    /// the next one; nothing is read from the input after it.
+    fn try_group_by<F, K, T, E>(self, f: F) -> TryGroupBy<Self, E, F, K>
+        where Self: Sized + Iterator<Item = Result<T, E>>,
+              F: FnMut(&T) -> K,
+              K: PartialEq
+    {
+        TryGroupBy::new(self, f)
+    }
+
+    /// Like `group_by`, for key functions that can fail.
+    ///
+    /// An item whose key is an `Err` is handed back in a `KeyError` along
+    /// with the error, in place of a group.
+    fn group_by_try_key<F, K, E>(self, f: F) -> TryKeyGroupBy<Self, F, K, E>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> Result<K, E>,
+              K: PartialEq
+    {
+        TryKeyGroupBy::new(self, f)
+    }
+
+    /// Like `group_by`, but yields an error instead of a second group for
+    /// a key whose group has already closed.
+    ///
+    /// Closed keys are remembered in a `HashSet`.
+    #[cfg(feature = "alloc")]
+    fn group_by_strict<F, K>(self, f: F) -> StrictGroupBy<Self, F, K, HashSet<K>>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Hash + Eq
+    {
+        self.group_by_strict_with(f, HashSet::new())
+    }
+
+    /// Like `group_by_strict`, but requires keys to strictly increase
+    /// instead of remembering them, so it also rejects unsorted keys that
+    /// never repeat.
+    fn group_by_monotonic<F, K>(self, f: F) -> StrictGroupBy<Self, F, K, Monotonic<K>>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Ord
+    {
+        self.group_by_strict_with(f, Monotonic::default())
+    }
+
+    /// Like `group_by_strict`, with `closed` deciding which keys may start
+    /// a new group.
+    fn group_by_strict_with<F, K, S>(self, f: F, closed: S) -> StrictGroupBy<Self, F, K, S>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: PartialEq,
+              S: ClosedKeys<K>
+    {
+        StrictGroupBy::new(GroupBy::new(self, f), closed)
+    }
+
+    /// Like `group_by`, but buffers each group and yields `(key, items)`
+    /// as a plain `Iterator`.
+    #[cfg(feature = "alloc")]
+    fn group_by_owned<F, K>(self, f: F) -> GroupByOwned<Self, F, K>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: PartialEq
+    {
+        GroupByOwned { group_by: GroupBy::new(self, f) }
+    }
+
+    /// Groups consecutive items for which `pred(&prev, &next)` holds,
+    /// where `prev` is the item right before `next`.
+    fn group_by_eq<P>(self, pred: P) -> GroupByEq<Self, P>
+        where Self: Sized + Iterator,
+              P: FnMut(&Self::Item, &Self::Item) -> bool
+    {
+        GroupByEq {
+            iter: AdjacentIter {
+                iter: self,
+                pred,
+                peeked: None,
+                started: false,
+                joined: false,
+            },
+            first: None,
+        }
+    }
+
+    /// Splits the items into sessions, starting a new one whenever an
+    /// item's timestamp lies more than `max_gap` outside the range of the
+    /// session so far, and yields `(session_start, session_end, items)`.
+    ///
+    /// A late event within the session's range, or within `max_gap` of
+    /// it, joins the session, and the start and end are the smallest and
+    /// largest timestamps in it. A session is closed by the first item
+    /// that does not join it, so events later than that start a new one.
+    /// `f` is called exactly once per element, in order.
+    #[cfg(feature = "alloc")]
+    fn session_by<F, T, D>(self, mut f: F, max_gap: D)
+        -> impl Iterator<Item = (T, T, Vec<Self::Item>)>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> T,
+              T: PartialOrd + Clone + Sub<Output = D>,
+              D: PartialOrd
+    {
+        let mut timed = self.map(move |item| (f(&item), item)).peekable();
+        iter::from_fn(move || {
+            let (mut start, first) = timed.next()?;
+            let mut end = start.clone();
+            let mut items = Vec::new();
+            items.push(first);
+            loop {
+                let joins = match timed.peek() {
+                    None => false,
+                    Some((time, _)) if *time > end => time.clone() - end.clone() <= max_gap,
+                    Some((time, _)) if *time < start => start.clone() - time.clone() <= max_gap,
+                    Some(_) => true
+                };
+                if !joins {
+                    break;
+                }
+                let (time, item) = timed.next().unwrap();
+                if time < start {
+                    start = time;
+                } else if time > end {
+                    end = time;
+                }
+                items.push(item);
+            }
+            Some((start, end, items))
+        })
+    }
+
+    /// Sorts the items by key, then groups them like `group_by`, so that
+    /// every key forms exactly one group.
+    ///
+    /// The whole input is buffered. The sort is stable and `f` is called
+    /// exactly once per element, in iteration order.
+    #[cfg(feature = "alloc")]
+    fn sorted_group_by<F, K>(self, mut f: F)
+        -> GroupBy<vec::IntoIter<Self::Item>, impl FnMut(&Self::Item) -> K, K>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Ord
+    {
+        let mut keyed: Vec<(K, Self::Item)> = self.map(|item| (f(&item), item)).collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        let (keys, items): (Vec<K>, Vec<Self::Item>) = keyed.into_iter().unzip();
+        // the group key function is called once per item in order, so it
+        // can hand out the precomputed keys one by one
+        let mut keys = keys.into_iter();
+        GroupBy::new(items.into_iter(), move |_| keys.next().unwrap())
+    }
+
+    /// Like `sorted_group_by`, for inputs too large to sort in memory.
+    ///
+    /// Runs that exceed the memory budget of `sort` are stable-sorted and
+    /// spilled to temporary files, which are merged back while grouping.
+    /// Keys are not stored in the runs, so `f` is called again each time a
+    /// spilled element is read back; this is why it must be `Fn`.
+    #[cfg(feature = "std")]
+    #[allow(clippy::type_complexity)]
+    fn external_group_by<F, K, C>(self, f: F, sort: ExternalSort<Self::Item, C>)
+        -> io::Result<ExternalGroupBy<Self::Item, F, K, C, impl FnMut(&Self::Item) -> K>>
+        where Self: Sized + Iterator,
+              F: Fn(&Self::Item) -> K,
+              K: Ord,
+              C: Codec<Self::Item>
+    {
+        external::external_group_by(self, f, sort)
+    }
+
+    /// Collects `(key, value)` pairs into a map from each key to all of its
+    /// values, whether or not they were contiguous.
+    #[cfg(feature = "alloc")]
+    fn into_group_map<K, V>(self) -> HashMap<K, Vec<V>>
+        where Self: Sized + Iterator<Item = (K, V)>,
+              K: Hash + Eq
+    {
+        let mut map = HashMap::new();
+        for (key, value) in self {
+            map.entry(key).or_insert_with(Vec::new).push(value);
+        }
+        map
+    }
+
+    /// Collects items into a map from `f(item)` to all items with that key,
+    /// whether or not they were contiguous.
+    #[cfg(feature = "alloc")]
+    fn into_group_map_by<F, K>(self, mut f: F) -> HashMap<K, Vec<Self::Item>>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Hash + Eq
+    {
+        self.map(|item| (f(&item), item)).into_group_map()
+    }
+
+    /// Like `into_group_map_by`, but returns the groups in the order their
+    /// keys first appeared.
+    #[cfg(feature = "alloc")]
+    fn into_ordered_group_map_by<F, K>(self, mut f: F) -> Vec<(K, Vec<Self::Item>)>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Hash + Eq
+    {
+        let mut map = HashMap::new();
+        for (i, item) in self.enumerate() {
+            map.entry(f(&item)).or_insert_with(|| (i, Vec::new())).1.push(item);
+        }
+        let mut groups: Vec<_> = map.into_iter().collect();
+        groups.sort_by_key(|&(_, (first, _))| first);
+        groups.into_iter().map(|(key, (_, items))| (key, items)).collect()
+    }
+
+    /// Collects items into groups ordered by `f(item)`, which support
+    /// range queries over keys.
+    #[cfg(feature = "alloc")]
+    fn into_sorted_groups_by<F, K>(self, mut f: F) -> SortedGroups<K, Self::Item>
+        where Self: Sized + Iterator,
+              F: FnMut(&Self::Item) -> K,
+              K: Ord
+    {
+        let mut groups = SortedGroups::new();
+        for item in self {
+            groups.push(f(&item), item);
+        }
+        groups
+    }
 }
 
 impl<T> GroupByIterator for T where T: Iterator { }
@@ -179,8 +805,8 @@ impl<T> GroupByIterator for T where T: Iterator { }
 
 #[cfg(test)]
 mod tests {
-    use std::vec::Vec;
-    use super::GroupByIterator;
+    use std::prelude::v1::*;
+    use super::{GroupByIterator, LendingIterator};
 
     #[test]
     fn it_works() {
@@ -202,4 +828,182 @@ mod tests {
             grp.by_ref().next().map(|(k, g)| (*k, g.collect::<Vec<i32>>()))
         );
     }
+
+    #[test]
+    fn for_each_visits_every_group() {
+        let mut seen = Vec::new();
+        vec![1,1,2,3,3,4].into_iter().group_by(|x| x/2).for_each(|(k, g)| {
+            seen.push((*k, g.collect::<Vec<i32>>()));
+        });
+        assert_eq!(vec![(0, vec![1,1]), (1, vec![2,3,3]), (2, vec![4])], seen);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn owned_groups_compose_with_std() {
+        let groups: Vec<(i32, Vec<i32>)> = vec![1,1,2,3,3,4].into_iter()
+            .group_by_owned(|x| x/2)
+            .filter(|(_, g)| g.len() > 1)
+            .collect();
+        assert_eq!(vec![(0, vec![1,1]), (1, vec![2,3,3])], groups);
+    }
+
+    #[test]
+    fn key_func_called_once_per_element() {
+        use std::cell::Cell;
+        let calls = Cell::new(0);
+        let mut grp = vec![1,1,2,3,3,4,5].into_iter().group_by(|x| {
+            calls.set(calls.get() + 1);
+            x/2
+        });
+        let mut n = 0;
+        while let Some((_, g)) = grp.next() {
+            n += g.take(1).count();
+        }
+        assert_eq!(3, n);
+        assert_eq!(7, calls.get());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn stateful_key_func() {
+        let mut n = 0;
+        let groups: Vec<(i32, Vec<char>)> = "abcdefg".chars()
+            .group_by_owned(|_| { n += 1; (n - 1) / 3 })
+            .collect();
+        assert_eq!(vec![
+            (0, vec!['a','b','c']),
+            (1, vec!['d','e','f']),
+            (2, vec!['g']),
+        ], groups);
+    }
+
+    #[test]
+    fn group_by_eq_compares_adjacent_items() {
+        let mut grp = vec![1,2,3,5,6,8].into_iter().group_by_eq(|a, b| a + 1 == *b);
+        assert_eq!(Some(vec![1,2]), grp.next().map(|g| g.take(2).collect::<Vec<i32>>()));
+        assert_eq!(Some(vec![5,6]), grp.next().map(|g| g.collect::<Vec<i32>>()));
+        assert_eq!(Some(vec![8]), grp.next().map(|g| g.collect::<Vec<i32>>()));
+        assert!(grp.next().is_none());
+    }
+
+    #[test]
+    fn aggregate_groups() {
+        let sums: Vec<(i32, i32)> = vec![1,1,2,3,3,4].into_iter()
+            .group_by(|x| x/2)
+            .aggregate(0, |acc, x| acc + x)
+            .collect();
+        assert_eq!(vec![(0, 2), (1, 8), (2, 4)], sums);
+
+        let labels: Vec<(i32, String)> = vec![1,1,2,3].into_iter()
+            .group_by(|x| x/2)
+            .fold_groups(|k| format!("{}:", k), |acc, x| acc + &x.to_string())
+            .collect();
+        assert_eq!(vec![(0, "0:11".to_string()), (1, "1:23".to_string())], labels);
+    }
+
+    #[test]
+    fn builtin_reducers() {
+        let data = vec![(1, 'a'), (1, 'b'), (2, 'c'), (3, 'd'), (3, 'e'), (3, 'f')];
+        let groups = || data.clone().into_iter().group_by(|&(k, _)| k);
+        assert_eq!(vec![(1, 2), (2, 1), (3, 3)], groups().counts().collect::<Vec<_>>());
+        assert_eq!(vec![(1, 'a'), (2, 'c'), (3, 'd')],
+                   groups().firsts().map(|(k, (_, c))| (k, c)).collect::<Vec<_>>());
+        assert_eq!(vec![(1, 'b'), (2, 'c'), (3, 'f')],
+                   groups().lasts().map(|(k, (_, c))| (k, c)).collect::<Vec<_>>());
+
+        let nums = || vec![3u32, 1, 2, 10, 20, 5].into_iter().group_by(|&x| x >= 10);
+        assert_eq!(vec![(false, 6), (true, 30), (false, 5)], nums().sums::<u32>().collect::<Vec<_>>());
+        assert_eq!(vec![(false, 2.0), (true, 15.0), (false, 5.0)], nums().means().collect::<Vec<_>>());
+        let latencies = vec![3u64, 4, 1 << 40, 1 << 40].into_iter().group_by(|&x| x > 10);
+        assert_eq!(vec![(false, 3.5), (true, (1u64 << 40) as f64)],
+                   latencies.means_by(|x| x as f64).collect::<Vec<_>>());
+        assert_eq!(vec![(false, 1), (true, 10), (false, 5)], nums().min_by_key(|&x| x).collect::<Vec<_>>());
+        assert_eq!(vec![(false, 3), (true, 20), (false, 5)], nums().max_by_key(|&x| x).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn visitor_sees_group_boundaries() {
+        use super::GroupVisitor;
+
+        struct Sections(String);
+
+        impl GroupVisitor<i32, i32> for Sections {
+            fn on_group_start(&mut self, key: &i32) {
+                self.0 += &format!("<{}>", key);
+            }
+
+            fn on_item(&mut self, _key: &i32, item: i32) {
+                self.0 += &item.to_string();
+            }
+
+            fn on_group_end(&mut self, key: &i32) {
+                self.0 += &format!("</{}>", key);
+            }
+        }
+
+        let mut out = Sections(String::new());
+        vec![1,1,2,3,3,4].into_iter().group_by(|x| x/2).visit(&mut out);
+        assert_eq!("<0>11</0><1>233</1><2>4</2>", out.0);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn sessions_split_on_gaps() {
+        let events = vec![(2u32, 'a'), (0, 'z'), (3, 'b'), (20, 'c'), (25, 'd'), (24, 'e'), (60, 'f')];
+        let sessions: Vec<(u32, u32, Vec<char>)> = events.into_iter()
+            .session_by(|&(t, _)| t, 5)
+            .map(|(start, end, items)| (start, end, items.into_iter().map(|(_, c)| c).collect()))
+            .collect();
+        assert_eq!(vec![
+            (0, 3, vec!['a', 'z', 'b']),
+            (20, 25, vec!['c', 'd', 'e']),
+            (60, 60, vec!['f']),
+        ], sessions);
+
+        // late events far from their neighbour but inside the session range
+        let sessions = |times: Vec<u32>| times.into_iter()
+            .session_by(|&t| t, 5)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 9, vec![0, 4, 8, 1, 9])], sessions(vec![0, 4, 8, 1, 9]));
+        assert_eq!(vec![(0, 16, vec![0, 4, 8, 12, 16, 0])], sessions(vec![0, 4, 8, 12, 16, 0]));
+        assert_eq!(vec![(10, 14, vec![10, 14]), (3, 3, vec![3])], sessions(vec![10, 14, 3]));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn hash_grouping_merges_separated_runs() {
+        let words = vec!["apple", "bean", "avocado", "beet", "cherry", "almond"];
+        let map = words.clone().into_iter().into_group_map_by(|w| w.chars().next().unwrap());
+        assert_eq!(3, map.len());
+        assert_eq!(vec!["apple", "avocado", "almond"], map[&'a']);
+        assert_eq!(vec!["bean", "beet"], map[&'b']);
+
+        let ordered = words.into_iter().into_ordered_group_map_by(|w| w.len());
+        assert_eq!(vec![
+            (5, vec!["apple"]),
+            (4, vec!["bean", "beet"]),
+            (7, vec!["avocado"]),
+            (6, vec!["cherry", "almond"]),
+        ], ordered);
+
+        let pairs = vec![(1, 'a'), (2, 'b'), (1, 'c')].into_iter().into_group_map();
+        assert_eq!(vec!['a', 'c'], pairs[&1]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn sorted_group_by_merges_keys() {
+        let mut grp = vec![(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd'), (3, 'e')].into_iter()
+            .sorted_group_by(|&(k, _)| k);
+        let mut groups = Vec::new();
+        while let Some((k, g)) = grp.next() {
+            groups.push((*k, g.map(|(_, c)| c).collect::<String>()));
+        }
+        assert_eq!(vec![
+            (1, "bd".to_string()),
+            (2, "ac".to_string()),
+            (3, "e".to_string()),
+        ], groups);
+    }
 }
@@ -0,0 +1,303 @@
+//! Parallel grouping with rayon.
+
+use rayon::current_num_threads;
+use rayon::iter::{FlattenIter, IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::vec as par_vec;
+use std::borrow::Borrow;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::iter::Flatten;
+use std::vec::{self, Vec};
+
+use GroupBySlice;
+
+
+/// Index of the group boundary closest to `mid` looking forward first, or
+/// `None` if the whole slice is one group.
+fn group_boundary<T, F, K>(slice: &[T], key_func: &F, mid: usize) -> Option<usize> where
+    F: Fn(&T) -> K,
+    K: PartialEq
+{
+    let key = key_func(&slice[mid]);
+    if let Some(i) = (mid + 1..slice.len()).find(|&i| key_func(&slice[i]) != key) {
+        return Some(i);
+    }
+    (1..mid + 1).rev().find(|&i| key_func(&slice[i - 1]) != key)
+}
+
+
+struct GroupProducer<'a, 'f, T, F> where
+    T: 'a,
+    F: 'f
+{
+    slice: &'a [T],
+    key_func: &'f F,
+}
+
+
+impl<'a, 'f, T, F, K> UnindexedProducer for GroupProducer<'a, 'f, T, F> where
+    T: Sync,
+    F: Fn(&T) -> K + Sync,
+    K: PartialEq + Send
+{
+    type Item = (K, &'a [T]);
+
+    fn split(self) -> (Self, Option<Self>) {
+        if self.slice.len() < 2 {
+            return (self, None);
+        }
+        match group_boundary(self.slice, self.key_func, self.slice.len() / 2) {
+            None => (self, None),
+            Some(i) => {
+                let (left, right) = self.slice.split_at(i);
+                (GroupProducer { slice: left, key_func: self.key_func },
+                 Some(GroupProducer { slice: right, key_func: self.key_func }))
+            }
+        }
+    }
+
+    fn fold_with<G>(self, folder: G) -> G where
+        G: Folder<Self::Item>
+    {
+        folder.consume_iter(self.slice.group_by_key(self.key_func))
+    }
+}
+
+
+/// Parallel counterpart of `SliceGroupBy`.
+pub struct ParSliceGroupBy<'a, T, F> where
+    T: 'a,
+{
+    slice: &'a [T],
+    key_func: F,
+}
+
+
+impl<'a, T, F, K> ParallelIterator for ParSliceGroupBy<'a, T, F> where
+    T: Sync,
+    F: Fn(&T) -> K + Sync + Send,
+    K: PartialEq + Send
+{
+    type Item = (K, &'a [T]);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result where
+        C: UnindexedConsumer<Self::Item>
+    {
+        let producer = GroupProducer { slice: self.slice, key_func: &self.key_func };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+
+pub trait ParGroupBySlice<T> {
+    /// Like `GroupBySlice::group_by_key`, but yields the groups as a rayon
+    /// `ParallelIterator`.
+    ///
+    /// The slice is only ever split between groups. `f` is shared between
+    /// threads and may be called more than once per element while looking
+    /// for split points.
+    fn par_group_by_key<F, K>(&self, f: F) -> ParSliceGroupBy<'_, T, F>
+        where F: Fn(&T) -> K + Sync + Send,
+              K: PartialEq + Send;
+}
+
+
+impl<T: Sync> ParGroupBySlice<T> for [T] {
+    fn par_group_by_key<F, K>(&self, f: F) -> ParSliceGroupBy<'_, T, F>
+        where F: Fn(&T) -> K + Sync + Send,
+              K: PartialEq + Send
+    {
+        ParSliceGroupBy { slice: self, key_func: f }
+    }
+}
+
+
+// Moves the groups of `right` into `left`, after any values `left` already
+// holds for the same key.
+fn merge_groups<K, V>(left: &mut HashMap<K, Vec<V>>, right: HashMap<K, Vec<V>>) where
+    K: Hash + Eq
+{
+    for (key, mut values) in right {
+        match left.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut().append(&mut values),
+            Entry::Vacant(entry) => { entry.insert(values); }
+        }
+    }
+}
+
+
+/// Groups built by `ParGroupMap::par_into_sharded_group_map`, split into
+/// shards that hold disjoint keys.
+#[derive(Debug, Clone)]
+pub struct ShardedGroups<K, V> {
+    // picks the shard of a key
+    hasher: RandomState,
+    shards: Vec<HashMap<K, Vec<V>>>,
+}
+
+
+impl<K, V> ShardedGroups<K, V> where
+    K: Hash + Eq
+{
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(HashMap::is_empty)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&[V]> where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        let shard = shard_of(&self.hasher, key, self.shards.len());
+        self.shards[shard].get(key).map(|group| &group[..])
+    }
+
+    /// All groups, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &[V])> {
+        self.shards.iter().flatten().map(|(key, group)| (key, &group[..]))
+    }
+
+    pub fn into_shards(self) -> Vec<HashMap<K, Vec<V>>> {
+        self.shards
+    }
+
+    /// Merges the shards into a single map.
+    ///
+    /// This rehashes every key on the calling thread; iterate over the
+    /// groups, possibly in parallel, to avoid that cost.
+    pub fn into_map(self) -> HashMap<K, Vec<V>> {
+        let mut map = HashMap::with_capacity_and_hasher(self.len(), self.hasher);
+        for shard in self.shards {
+            map.extend(shard);
+        }
+        map
+    }
+}
+
+
+impl<K, V> IntoIterator for ShardedGroups<K, V> {
+    type Item = (K, Vec<V>);
+    type IntoIter = Flatten<vec::IntoIter<HashMap<K, Vec<V>>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.shards.into_iter().flatten()
+    }
+}
+
+
+impl<K, V> IntoParallelIterator for ShardedGroups<K, V> where
+    K: Send,
+    V: Send
+{
+    type Item = (K, Vec<V>);
+    type Iter = FlattenIter<par_vec::IntoIter<HashMap<K, Vec<V>>>>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.shards.into_par_iter().flatten_iter()
+    }
+}
+
+
+fn shard_of<Q>(hasher: &RandomState, key: &Q, shard_count: usize) -> usize where
+    Q: Hash + ?Sized
+{
+    (hasher.hash_one(key) % shard_count as u64) as usize
+}
+
+
+pub trait ParGroupMap: ParallelIterator {
+    /// Like `GroupByIterator::into_group_map`, for parallel iterators.
+    ///
+    /// Groups are collected as by `par_into_sharded_group_map`, after which
+    /// the shards are poured into one map on the calling thread, hashing
+    /// each distinct key once more. Values keep the order of the iterator,
+    /// so the result is the same as that of the sequential version.
+    fn par_into_group_map<K, V>(self) -> HashMap<K, Vec<V>>
+        where Self: ParallelIterator<Item = (K, V)>,
+              K: Hash + Eq + Send,
+              V: Send
+    {
+        self.par_into_sharded_group_map().into_map()
+    }
+
+    /// Like `par_into_group_map`, but returns the groups split into shards
+    /// of the key hash space, so that no step runs on a single thread.
+    ///
+    /// Each thread collects into its own maps, one per shard, and matching
+    /// shards are merged in parallel.
+    fn par_into_sharded_group_map<K, V>(self) -> ShardedGroups<K, V>
+        where Self: ParallelIterator<Item = (K, V)>,
+              K: Hash + Eq + Send,
+              V: Send
+    {
+        let hasher = RandomState::new();
+        let shard_count = current_num_threads();
+        let new_shards = || (0..shard_count).map(|_| HashMap::new()).collect::<Vec<_>>();
+        let shards = self
+            .fold(new_shards, |mut shards, (key, value)| {
+                let shard = shard_of(&hasher, &key, shard_count);
+                shards[shard].entry(key).or_insert_with(Vec::new).push(value);
+                shards
+            })
+            .reduce(new_shards, |mut left, right| {
+                left.par_iter_mut()
+                    .zip(right.into_par_iter())
+                    .for_each(|(left, right)| merge_groups(left, right));
+                left
+            });
+        ShardedGroups { hasher, shards }
+    }
+
+    /// Like `GroupByIterator::into_group_map_by`, for parallel iterators.
+    fn par_into_group_map_by<F, K>(self, f: F) -> HashMap<K, Vec<Self::Item>>
+        where F: Fn(&Self::Item) -> K + Sync + Send,
+              K: Hash + Eq + Send
+    {
+        self.map(|item| (f(&item), item)).par_into_group_map()
+    }
+}
+
+impl<P> ParGroupMap for P where P: ParallelIterator { }
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    use super::{ParGroupBySlice, ParGroupMap};
+    use {GroupByIterator, GroupBySlice};
+
+    #[test]
+    fn matches_sequential_grouping() {
+        let data: Vec<u64> = (0..100_000).map(|x| x * x / 30_011).collect();
+        let sequential: Vec<(u64, u64)> = data.group_by_key(|&x| x)
+            .map(|(k, g)| (k, g.iter().sum()))
+            .collect();
+        let parallel: Vec<(u64, u64)> = data.par_group_by_key(|&x| x)
+            .map(|(k, g)| (k, g.iter().sum()))
+            .collect();
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn sharded_map_matches_sequential() {
+        let sequential = (0..50_000u32).into_group_map_by(|x| x % 97);
+        let parallel = (0..50_000u32).into_par_iter().par_into_group_map_by(|x| x % 97);
+        assert_eq!(sequential, parallel);
+
+        let sharded = (0..50_000u32).into_par_iter()
+            .map(|x| (x % 97, x))
+            .par_into_sharded_group_map();
+        assert_eq!(97, sharded.len());
+        assert_eq!(sequential.get(&5).map(|g| &g[..]), sharded.get(&5));
+        assert_eq!(None, sharded.get(&97));
+        let total: usize = sharded.clone().into_par_iter().map(|(_, g)| g.len()).sum();
+        assert_eq!(50_000, total);
+        assert_eq!(sequential, sharded.into_map());
+    }
+}
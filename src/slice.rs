@@ -0,0 +1,360 @@
+use core::cmp;
+use core::iter::FusedIterator;
+use core::mem;
+
+
+// Finds group boundaries at either end of a slice, computing each
+// element's key at most once.
+struct GroupEnds<F, K> {
+    key_func: F,
+    // cached keys of the first and last element of the remaining slice
+    front_key: Option<K>,
+    back_key: Option<K>,
+}
+
+
+impl<F, K> GroupEnds<F, K> {
+    fn new(key_func: F) -> Self {
+        GroupEnds { key_func, front_key: None, back_key: None }
+    }
+
+    fn key_at<T>(&mut self, slice: &[T], i: usize) -> K where
+        F: FnMut(&T) -> K
+    {
+        let front = if i == 0 { self.front_key.take() } else { None };
+        let back = if i == slice.len() - 1 { self.back_key.take() } else { None };
+        match front.or(back) {
+            Some(key) => key,
+            None => (self.key_func)(&slice[i])
+        }
+    }
+
+    // key and length of the first group of a non-empty slice
+    fn front<T>(&mut self, slice: &[T]) -> (K, usize) where
+        F: FnMut(&T) -> K,
+        K: PartialEq
+    {
+        let key = self.key_at(slice, 0);
+        let mut end = 1;
+        while end < slice.len() {
+            let next_key = self.key_at(slice, end);
+            if next_key != key {
+                self.front_key = Some(next_key);
+                break;
+            }
+            end += 1;
+        }
+        (key, end)
+    }
+
+    // key and start index of the last group of a non-empty slice
+    fn back<T>(&mut self, slice: &[T]) -> (K, usize) where
+        F: FnMut(&T) -> K,
+        K: PartialEq
+    {
+        let key = self.key_at(slice, slice.len() - 1);
+        let mut start = slice.len() - 1;
+        while start > 0 {
+            let prev_key = self.key_at(slice, start - 1);
+            if prev_key != key {
+                self.back_key = Some(prev_key);
+                break;
+            }
+            start -= 1;
+        }
+        (key, start)
+    }
+}
+
+
+pub struct SliceGroupBy<'a, T, F, K> where
+    T: 'a,
+    F: FnMut(&T) -> K,
+{
+    slice: &'a [T],
+    ends: GroupEnds<F, K>,
+}
+
+
+impl<'a, T, F, K> Iterator for SliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    type Item = (K, &'a [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.slice.is_empty() {
+            return None;
+        }
+        let (key, end) = self.ends.front(self.slice);
+        let (group, rest) = self.slice.split_at(end);
+        self.slice = rest;
+        Some((key, group))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::from(!self.slice.is_empty()), Some(self.slice.len()))
+    }
+}
+
+
+impl<'a, T, F, K> DoubleEndedIterator for SliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.slice.is_empty() {
+            return None;
+        }
+        let (key, start) = self.ends.back(self.slice);
+        let (rest, group) = self.slice.split_at(start);
+        self.slice = rest;
+        Some((key, group))
+    }
+}
+
+
+impl<'a, T, F, K> FusedIterator for SliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{ }
+
+
+pub struct SliceGroupByMut<'a, T, F, K> where
+    T: 'a,
+    F: FnMut(&T) -> K,
+{
+    slice: &'a mut [T],
+    ends: GroupEnds<F, K>,
+}
+
+
+impl<'a, T, F, K> Iterator for SliceGroupByMut<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    type Item = (K, &'a mut [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.slice.is_empty() {
+            return None;
+        }
+        let (key, end) = self.ends.front(self.slice);
+        let (group, rest) = mem::take(&mut self.slice).split_at_mut(end);
+        self.slice = rest;
+        Some((key, group))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::from(!self.slice.is_empty()), Some(self.slice.len()))
+    }
+}
+
+
+impl<'a, T, F, K> DoubleEndedIterator for SliceGroupByMut<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.slice.is_empty() {
+            return None;
+        }
+        let (key, start) = self.ends.back(self.slice);
+        let (rest, group) = mem::take(&mut self.slice).split_at_mut(start);
+        self.slice = rest;
+        Some((key, group))
+    }
+}
+
+
+impl<'a, T, F, K> FusedIterator for SliceGroupByMut<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: PartialEq
+{ }
+
+
+// Length of the run of elements with key `key` at the start of `slice`,
+// which must be sorted by key and start with such an element.
+fn gallop_front<T, F, K>(slice: &[T], key_func: &mut F, key: &K) -> usize where
+    F: FnMut(&T) -> K,
+    K: Ord
+{
+    let mut lo = 1;
+    let mut bound = 1;
+    while bound < slice.len() && key_func(&slice[bound]) <= *key {
+        lo = bound + 1;
+        bound *= 2;
+    }
+    let hi = cmp::min(bound, slice.len());
+    lo + slice[lo..hi].partition_point(|item| key_func(item) <= *key)
+}
+
+
+// Start of the run of elements with key `key` at the end of `slice`,
+// which must be sorted by key and end with such an element.
+fn gallop_back<T, F, K>(slice: &[T], key_func: &mut F, key: &K) -> usize where
+    F: FnMut(&T) -> K,
+    K: Ord
+{
+    let len = slice.len();
+    let mut hi = len - 1;
+    let mut bound = 1;
+    while bound < len && key_func(&slice[len - 1 - bound]) >= *key {
+        hi = len - 1 - bound;
+        bound *= 2;
+    }
+    let lo = len - cmp::min(bound + 1, len);
+    lo + slice[lo..hi].partition_point(|item| key_func(item) < *key)
+}
+
+
+pub struct SortedSliceGroupBy<'a, T, F, K> where
+    T: 'a,
+    F: FnMut(&T) -> K,
+{
+    slice: &'a [T],
+    key_func: F,
+}
+
+
+impl<'a, T, F, K> Iterator for SortedSliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: Ord
+{
+    type Item = (K, &'a [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = (self.key_func)(self.slice.first()?);
+        let end = gallop_front(self.slice, &mut self.key_func, &key);
+        let (group, rest) = self.slice.split_at(end);
+        self.slice = rest;
+        Some((key, group))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::from(!self.slice.is_empty()), Some(self.slice.len()))
+    }
+}
+
+
+impl<'a, T, F, K> DoubleEndedIterator for SortedSliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: Ord
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let key = (self.key_func)(self.slice.last()?);
+        let start = gallop_back(self.slice, &mut self.key_func, &key);
+        let (rest, group) = self.slice.split_at(start);
+        self.slice = rest;
+        Some((key, group))
+    }
+}
+
+
+impl<'a, T, F, K> FusedIterator for SortedSliceGroupBy<'a, T, F, K> where
+    F: FnMut(&T) -> K,
+    K: Ord
+{ }
+
+
+pub trait GroupBySlice<T> {
+    /// Splits the slice into runs of consecutive elements with equal keys.
+    ///
+    /// As with `GroupByIterator::group_by`, `f` is called exactly once per
+    /// element, although not in order when iterating from both ends.
+    fn group_by_key<F, K>(&self, f: F) -> SliceGroupBy<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: PartialEq;
+
+    /// Like `group_by_key`, but yields mutable runs so each group can be
+    /// rearranged in place.
+    fn group_by_key_mut<F, K>(&mut self, f: F) -> SliceGroupByMut<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: PartialEq;
+
+    /// Like `group_by_key`, for slices already sorted by key.
+    ///
+    /// Group ends are found by exponential and then binary search, so `f`
+    /// is called O(log n) times per group of length n rather than once per
+    /// element. The result is unspecified if the slice is not sorted.
+    fn group_by_sorted_key<F, K>(&self, f: F) -> SortedSliceGroupBy<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: Ord;
+}
+
+
+impl<T> GroupBySlice<T> for [T] {
+    fn group_by_key<F, K>(&self, f: F) -> SliceGroupBy<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: PartialEq
+    {
+        SliceGroupBy { slice: self, ends: GroupEnds::new(f) }
+    }
+
+    fn group_by_key_mut<F, K>(&mut self, f: F) -> SliceGroupByMut<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: PartialEq
+    {
+        SliceGroupByMut { slice: self, ends: GroupEnds::new(f) }
+    }
+
+    fn group_by_sorted_key<F, K>(&self, f: F) -> SortedSliceGroupBy<'_, T, F, K>
+        where F: FnMut(&T) -> K,
+              K: Ord
+    {
+        SortedSliceGroupBy { slice: self, key_func: f }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use super::GroupBySlice;
+
+    #[test]
+    fn groups_from_both_ends() {
+        let data = [1,1,2,3,3,4,6];
+        let mut calls = 0;
+        let mut grp = data.group_by_key(|x| { calls += 1; x/2 });
+        assert_eq!(Some((0, &[1,1][..])), grp.next());
+        assert_eq!(Some((3, &[6][..])), grp.next_back());
+        assert_eq!(Some((2, &[4][..])), grp.next_back());
+        assert_eq!(Some((1, &[2,3,3][..])), grp.next());
+        assert_eq!(None, grp.next());
+        assert_eq!(None, grp.next_back());
+        assert_eq!(data.len(), calls);
+    }
+
+    #[test]
+    fn sort_within_groups() {
+        let mut data = [(1, 'c'), (1, 'a'), (2, 'b'), (3, 'z'), (3, 'y')];
+        for (_, group) in data.group_by_key_mut(|&(k, _)| k) {
+            group.sort();
+        }
+        assert_eq!([(1, 'a'), (1, 'c'), (2, 'b'), (3, 'y'), (3, 'z')], data);
+        let mut grp = data.group_by_key_mut(|&(k, _)| k);
+        assert_eq!(Some(3), grp.next_back().map(|(k, g)| { g[0].1 = 'x'; k }));
+        assert_eq!(Some(2), grp.next_back().map(|(k, _)| k));
+        assert_eq!(Some(1), grp.next().map(|(k, _)| k));
+        assert!(grp.next().is_none());
+        assert_eq!((3, 'x'), data[3]);
+    }
+
+    #[test]
+    fn sorted_groups_match_linear_scan() {
+        let data: Vec<u32> = (0..1000).map(|x| x * x / 997).collect();
+        let linear: Vec<_> = data.group_by_key(|&x| x).collect();
+        let sorted: Vec<_> = data.group_by_sorted_key(|&x| x).collect();
+        assert_eq!(linear, sorted);
+        let mut backwards: Vec<_> = data.group_by_sorted_key(|&x| x).rev().collect();
+        backwards.reverse();
+        assert_eq!(linear, backwards);
+
+        let mut calls = 0;
+        assert_eq!(10, (0..1000).collect::<Vec<u32>>()
+            .group_by_sorted_key(|&x| { calls += 1; x / 100 })
+            .count());
+        assert!(calls < 200);
+    }
+}
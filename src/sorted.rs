@@ -0,0 +1,97 @@
+use alloc::collections::{btree_map, BTreeMap};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::ops::RangeBounds;
+
+
+/// Groups ordered by key, built by `GroupByIterator::into_sorted_groups_by`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortedGroups<K, V> {
+    map: BTreeMap<K, Vec<V>>,
+}
+
+
+impl<K, V> SortedGroups<K, V> where
+    K: Ord
+{
+    pub(crate) fn new() -> Self {
+        SortedGroups { map: BTreeMap::new() }
+    }
+
+    pub(crate) fn push(&mut self, key: K, value: V) {
+        self.map.entry(key).or_default().push(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&[V]> where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized
+    {
+        self.map.get(key).map(|group| &group[..])
+    }
+
+    pub fn first_group(&self) -> Option<(&K, &[V])> {
+        self.map.iter().next().map(|(key, group)| (key, &group[..]))
+    }
+
+    pub fn last_group(&self) -> Option<(&K, &[V])> {
+        self.map.iter().next_back().map(|(key, group)| (key, &group[..]))
+    }
+
+    /// Groups whose keys fall within `range`, in key order.
+    pub fn range<Q, R>(&self, range: R) -> impl DoubleEndedIterator<Item = (&K, &[V])> where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>
+    {
+        self.map.range(range).map(|(key, group)| (key, &group[..]))
+    }
+
+    /// All groups in key order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &[V])> {
+        self.map.iter().map(|(key, group)| (key, &group[..]))
+    }
+
+    pub fn into_map(self) -> BTreeMap<K, Vec<V>> {
+        self.map
+    }
+}
+
+
+impl<K, V> IntoIterator for SortedGroups<K, V> {
+    type Item = (K, Vec<V>);
+    type IntoIter = btree_map::IntoIter<K, Vec<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use GroupByIterator;
+
+    #[test]
+    fn range_queries() {
+        let groups = vec![30, 12, 5, 17, 38, 11, 3].into_iter()
+            .into_sorted_groups_by(|x| x / 10);
+        assert_eq!(3, groups.len());
+        assert_eq!(Some((&0, &[5, 3][..])), groups.first_group());
+        assert_eq!(Some((&3, &[30, 38][..])), groups.last_group());
+        assert_eq!(
+            vec![(&1, &[12, 17, 11][..]), (&3, &[30, 38][..])],
+            groups.range(1..).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![&0, &1], groups.range(..=2).map(|(k, _)| k).collect::<Vec<_>>());
+        assert_eq!(None, groups.get(&9));
+    }
+}
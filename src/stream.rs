@@ -0,0 +1,328 @@
+//! `group_by` over an asynchronous `Stream`.
+//!
+//! ```
+//! # extern crate futures;
+//! # extern crate groupby;
+//! use futures::executor::block_on;
+//! use futures::stream::{self, StreamExt};
+//! use groupby::GroupByStream;
+//!
+//! # fn main() {
+//! let mut groups = stream::iter(vec![1, 1, 2, 3, 3, 4]).group_by(|x| x / 2);
+//! while let Some((key, grp)) = block_on(groups.next()) {
+//!     println!("Key {:?}: {:?}", key, block_on(grp.collect::<Vec<_>>()));
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::future::Future;
+#[cfg(feature = "alloc")]
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+#[cfg(feature = "alloc")]
+use futures_core::FusedStream;
+use futures_core::Stream;
+
+
+macro_rules! ready {
+    ( $e:expr ) => (
+        match $e {
+            Poll::Ready(value) => value,
+            Poll::Pending => return Poll::Pending,
+        }
+    );
+}
+
+
+struct KeyedStream<S, F, K> where
+    S: Stream,
+{
+    stream: S,
+    key_func: F,
+    peeked: Option<(K, S::Item)>,
+    done: bool,
+}
+
+
+impl<S, F, K> KeyedStream<S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+{
+    // Makes sure `peeked` holds the next item, unless the stream has ended.
+    fn poll_peek(&mut self, cx: &mut Context) -> Poll<()> {
+        if self.peeked.is_none() && !self.done {
+            match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+                Some(item) => self.peeked = Some(((self.key_func)(&item), item)),
+                None => self.done = true,
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+
+pub struct GroupStream<'a, S, F, K> where
+    S: Stream + 'a,
+    F: 'a,
+    K: 'a
+{
+    stream: &'a mut KeyedStream<S, F, K>,
+    first: &'a mut Option<S::Item>,
+    key: &'a K,
+}
+
+
+impl<'a, S, F, K> Stream for GroupStream<'a, S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        let this = self.get_mut();
+        if let Some(item) = this.first.take() {
+            return Poll::Ready(Some(item));
+        }
+        ready!(this.stream.poll_peek(cx));
+        let same_key = match this.stream.peeked {
+            Some((ref key, _)) => key == this.key,
+            None => false
+        };
+        if same_key {
+            Poll::Ready(this.stream.peeked.take().map(|(_, item)| item))
+        } else {
+            Poll::Ready(None)
+        }
+    }
+}
+
+
+/// Groups consecutive items of a stream with equal keys.
+///
+/// Like `GroupBy`, groups borrow from it, so they are obtained by awaiting
+/// `next()` rather than through `Stream`.
+pub struct StreamGroupBy<S, F, K> where
+    S: Stream,
+{
+    stream: KeyedStream<S, F, K>,
+    first: Option<S::Item>,
+    current_key: Option<K>,
+}
+
+
+impl<S, F, K> StreamGroupBy<S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq
+{
+    fn new(stream: S, key_func: F) -> Self {
+        StreamGroupBy {
+            stream: KeyedStream { stream, key_func, peeked: None, done: false },
+            first: None,
+            current_key: None,
+        }
+    }
+
+    fn poll_skip_to_next_key(&mut self, cx: &mut Context) -> Poll<bool> {
+        self.first = None;
+        loop {
+            ready!(self.stream.poll_peek(cx));
+            let same_key = match self.stream.peeked {
+                None => return Poll::Ready(false),
+                Some((ref key, _)) => Some(key) == self.current_key.as_ref()
+            };
+            let (key, item) = self.stream.peeked.take().unwrap();
+            if !same_key {
+                // the key moves into `current_key`, the item is handed out first
+                self.current_key = Some(key);
+                self.first = Some(item);
+                return Poll::Ready(true);
+            }
+        }
+    }
+
+    /// Resolves to the next group, or `None` once the stream has ended.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> NextGroup<'_, S, F, K> {
+        NextGroup { group_by: Some(self) }
+    }
+}
+
+
+pub struct NextGroup<'a, S, F, K> where
+    S: Stream + 'a,
+    F: 'a,
+    K: 'a
+{
+    group_by: Option<&'a mut StreamGroupBy<S, F, K>>,
+}
+
+
+impl<'a, S, F, K> Future for NextGroup<'a, S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq
+{
+    type Output = Option<(&'a K, GroupStream<'a, S, F, K>)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let found = ready!(this.group_by.as_mut().expect("polled after completion")
+                               .poll_skip_to_next_key(cx));
+        let group_by = this.group_by.take().unwrap();
+        if !found {
+            return Poll::Ready(None);
+        }
+        let StreamGroupBy { ref mut stream, ref mut first, ref current_key } = *group_by;
+        let key = current_key.as_ref().unwrap();
+        Poll::Ready(Some((key, GroupStream { stream, first, key })))
+    }
+}
+
+
+/// Buffers each group of a stream, yielding `(key, items)` pairs.
+#[cfg(feature = "alloc")]
+pub struct StreamGroupByOwned<S, F, K> where
+    S: Stream,
+{
+    group_by: StreamGroupBy<S, F, K>,
+    // items of the group being collected
+    items: Vec<S::Item>,
+}
+
+
+// the wrapped stream is never pinned in place, see `KeyedStream::poll_peek`
+#[cfg(feature = "alloc")]
+impl<S, F, K> Unpin for StreamGroupByOwned<S, F, K> where
+    S: Stream
+{ }
+
+
+#[cfg(feature = "alloc")]
+impl<S, F, K> Stream for StreamGroupByOwned<S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq
+{
+    type Item = (K, Vec<S::Item>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let group_by = &mut this.group_by;
+        if group_by.current_key.is_none() {
+            if !ready!(group_by.poll_skip_to_next_key(cx)) {
+                return Poll::Ready(None);
+            }
+            this.items.extend(group_by.first.take());
+        }
+        loop {
+            ready!(group_by.stream.poll_peek(cx));
+            match group_by.stream.peeked {
+                Some((ref key, _)) if Some(key) == group_by.current_key.as_ref() => {},
+                _ => break
+            }
+            this.items.extend(group_by.stream.peeked.take().map(|(_, item)| item));
+        }
+        // the group was consumed entirely, so the next poll starts afresh
+        let key = group_by.current_key.take().unwrap();
+        Poll::Ready(Some((key, mem::take(&mut this.items))))
+    }
+}
+
+
+#[cfg(feature = "alloc")]
+impl<S, F, K> FusedStream for StreamGroupByOwned<S, F, K> where
+    S: Stream + Unpin,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq
+{
+    fn is_terminated(&self) -> bool {
+        let stream = &self.group_by.stream;
+        stream.done && stream.peeked.is_none() && self.group_by.current_key.is_none()
+    }
+}
+
+
+pub trait GroupByStream: Stream {
+    /// Like `GroupByIterator::group_by`, for streams.
+    fn group_by<F, K>(self, f: F) -> StreamGroupBy<Self, F, K>
+        where Self: Sized + Unpin,
+              F: FnMut(&Self::Item) -> K,
+              K: PartialEq
+    {
+        StreamGroupBy::new(self, f)
+    }
+
+    /// Like `GroupByIterator::group_by_owned`, for streams.
+    #[cfg(feature = "alloc")]
+    fn group_by_owned<F, K>(self, f: F) -> StreamGroupByOwned<Self, F, K>
+        where Self: Sized + Unpin,
+              F: FnMut(&Self::Item) -> K,
+              K: PartialEq
+    {
+        StreamGroupByOwned { group_by: StreamGroupBy::new(self, f), items: Vec::new() }
+    }
+}
+
+impl<S> GroupByStream for S where S: Stream { }
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use futures::executor::LocalPool;
+    use futures::stream::{self, Stream, StreamExt};
+    use super::GroupByStream;
+
+    // Returns `Pending` before every item, as a slow source would.
+    struct Stuttering<S> {
+        stream: S,
+        ready: bool,
+    }
+
+    impl<S: Stream + Unpin> Stream for Stuttering<S> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+            if !self.ready {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.ready = false;
+            Pin::new(&mut self.stream).poll_next(cx)
+        }
+    }
+
+    fn stuttering(items: Vec<i32>) -> Stuttering<stream::Iter<::std::vec::IntoIter<i32>>> {
+        Stuttering { stream: stream::iter(items), ready: false }
+    }
+
+    #[test]
+    fn borrowed_groups() {
+        let mut pool = LocalPool::new();
+        let mut grp = stuttering(vec![1, 1, 1, 2, 3, 3, 4]).group_by(|x| x / 2);
+        let mut groups = Vec::new();
+        while let Some((key, g)) = pool.run_until(grp.next()) {
+            groups.push((*key, pool.run_until(g.take(2).collect::<Vec<_>>())));
+        }
+        assert_eq!(vec![(0, vec![1, 1]), (1, vec![2, 3]), (2, vec![4])], groups);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn owned_groups() {
+        let mut pool = LocalPool::new();
+        let groups: Vec<(i32, Vec<i32>)> = pool.run_until(
+            stuttering(vec![1, 1, 2, 3, 3, 4]).group_by_owned(|x| x / 2).collect()
+        );
+        assert_eq!(vec![(0, vec![1, 1]), (1, vec![2, 3, 3]), (2, vec![4])], groups);
+    }
+}
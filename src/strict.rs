@@ -0,0 +1,176 @@
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeSet;
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "alloc")]
+use core::hash::Hash;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use hashbrown::HashSet;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+
+use {GroupBy, GroupIter, LendingIterator};
+
+
+/// A key that starts a group although an earlier group with the same key
+/// has already closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonContiguousKey<K> {
+    pub key: K,
+    /// Zero-based position of the item starting the offending group.
+    pub position: usize,
+}
+
+
+impl<K: fmt::Debug> fmt::Display for NonContiguousKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key {:?} at position {} reappears after its group closed", self.key, self.position)
+    }
+}
+
+
+impl<K: fmt::Debug> Error for NonContiguousKey<K> { }
+
+
+/// Keeps track of the keys of closed groups for `StrictGroupBy`.
+pub trait ClosedKeys<K> {
+    fn close(&mut self, key: K);
+
+    /// Whether a group with `key` may start now.
+    fn may_open(&self, key: &K) -> bool;
+}
+
+
+#[cfg(feature = "alloc")]
+impl<K: Hash + Eq> ClosedKeys<K> for HashSet<K> {
+    fn close(&mut self, key: K) {
+        self.insert(key);
+    }
+
+    fn may_open(&self, key: &K) -> bool {
+        !self.contains(key)
+    }
+}
+
+
+#[cfg(feature = "alloc")]
+impl<K: Ord> ClosedKeys<K> for BTreeSet<K> {
+    fn close(&mut self, key: K) {
+        self.insert(key);
+    }
+
+    fn may_open(&self, key: &K) -> bool {
+        !self.contains(key)
+    }
+}
+
+
+/// Only remembers the last closed key, and accepts keys greater than it.
+#[derive(Debug, Clone)]
+pub struct Monotonic<K> {
+    last: Option<K>,
+}
+
+
+impl<K> Default for Monotonic<K> {
+    fn default() -> Self {
+        Monotonic { last: None }
+    }
+}
+
+
+impl<K: Ord> ClosedKeys<K> for Monotonic<K> {
+    fn close(&mut self, key: K) {
+        self.last = Some(key);
+    }
+
+    fn may_open(&self, key: &K) -> bool {
+        self.last.as_ref().is_none_or(|last| key > last)
+    }
+}
+
+
+/// Groups that must not repeat a key. On error the offending group is
+/// skipped entirely, and the next call continues with the group after it.
+pub struct StrictGroupBy<I, F, K, S> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+{
+    group_by: GroupBy<I, F, K>,
+    closed: S,
+}
+
+
+impl<I, F, K, S> StrictGroupBy<I, F, K, S> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq,
+    S: ClosedKeys<K>
+{
+    pub(crate) fn new(group_by: GroupBy<I, F, K>, closed: S) -> Self {
+        StrictGroupBy { group_by, closed }
+    }
+}
+
+
+impl<I, F, K, S> LendingIterator for StrictGroupBy<I, F, K, S> where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq,
+    S: ClosedKeys<K>
+{
+    type Item<'a> = Result<(&'a K, GroupIter<'a, I, F, K>), NonContiguousKey<K>> where Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>> {
+        let group_by = &mut self.group_by;
+        group_by.skip_group();
+        if let Some(key) = group_by.current_key.take() {
+            self.closed.close(key);
+        }
+        if !group_by.skip_to_next_key() {
+            return None;
+        }
+        let position = group_by.iter.position - 1;
+        if !self.closed.may_open(group_by.current_key.as_ref().unwrap()) {
+            group_by.skip_group();
+            let key = group_by.current_key.take().unwrap();
+            return Some(Err(NonContiguousKey { key, position }));
+        }
+        Some(Ok(group_by.current_group()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+    use super::NonContiguousKey;
+    use {GroupByIterator, LendingIterator};
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn reports_reappearing_keys() {
+        let mut grp = vec![1, 1, 2, 1, 1, 3, 2].into_iter().group_by_strict(|&x| x);
+        let mut results = Vec::new();
+        while let Some(group) = grp.next() {
+            results.push(group.map(|(k, g)| (*k, g.count())));
+        }
+        assert_eq!(vec![
+            Ok((1, 2)),
+            Ok((2, 1)),
+            Err(NonContiguousKey { key: 1, position: 3 }),
+            Ok((3, 1)),
+            Err(NonContiguousKey { key: 2, position: 6 }),
+        ], results);
+    }
+
+    #[test]
+    fn reports_non_monotonic_keys() {
+        let mut grp = vec![1, 3, 3, 2, 4].into_iter().group_by_monotonic(|&x| x);
+        let mut results = Vec::new();
+        while let Some(group) = grp.next() {
+            results.push(group.map(|(k, _)| *k));
+        }
+        assert_eq!(vec![Ok(1), Ok(3), Err(NonContiguousKey { key: 2, position: 3 }), Ok(4)], results);
+    }
+}